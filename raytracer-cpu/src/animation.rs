@@ -0,0 +1,255 @@
+//! Keyframed animation.
+//!
+//! Times are in seconds. Objects are moved by [`AnimatedTransform`], which
+//! is evaluated at the time of every ray so moving objects get motion blur
+//! over the shutter interval of the camera. Camera paths and material
+//! parameters are [`Keyframes`] evaluated once per frame.
+
+use std::ops::{Add, Mul};
+
+use crate::{
+    boundind_box::BoundingBox,
+    hittables::{Affine, ArcHittable, HitRecord, Hittable},
+    interval::Interval,
+    matrix::Mat4,
+    ray::Ray,
+    utils::lerp,
+    vector::Vec3,
+};
+
+/// Value changing over time, linearly interpolated between keyframes and
+/// constant before the first and after the last one
+#[derive(Debug, Clone)]
+pub struct Keyframes<T> {
+    /// Sorted by time
+    keys: Vec<(f64, T)>,
+}
+
+impl<T> Keyframes<T>
+where
+    T: Copy + Add<Output = T>,
+    f64: Mul<T, Output = T>,
+{
+    /// Panics if `keys` is empty
+    pub fn new(mut keys: Vec<(f64, T)>) -> Self {
+        assert!(!keys.is_empty(), "Keyframes need at least one key");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    pub fn constant(value: T) -> Self {
+        Self {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    pub fn is_constant(&self) -> bool {
+        self.keys.len() == 1
+    }
+
+    pub fn times(&self) -> impl Iterator<Item = f64> + '_ {
+        self.keys.iter().map(|(time, _)| *time)
+    }
+
+    pub fn at(&self, time: f64) -> T {
+        let next = self.keys.partition_point(|(t, _)| *t <= time);
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].1;
+        }
+
+        let (t0, v0) = self.keys[next - 1];
+        let (t1, v1) = self.keys[next];
+        lerp(v0, v1, (time - t0) / (t1 - t0))
+    }
+}
+
+/// Step of an animated transformation, see the builders of [`Mat4`]
+#[derive(Debug, Clone)]
+pub enum TransformOp {
+    Translate(Keyframes<Vec3>),
+    /// Rotations are in degrees
+    RotateX(Keyframes<f64>),
+    RotateY(Keyframes<f64>),
+    RotateZ(Keyframes<f64>),
+    Scale(Keyframes<Vec3>),
+    Matrix(Mat4),
+}
+
+impl TransformOp {
+    /// Apply the step at `time` after `matrix`
+    fn apply(&self, matrix: Mat4, time: f64) -> Mat4 {
+        match self {
+            Self::Translate(offset) => matrix.translate(offset.at(time)),
+            Self::RotateX(angle) => matrix.rotate_x(angle.at(time)),
+            Self::RotateY(angle) => matrix.rotate_y(angle.at(time)),
+            Self::RotateZ(angle) => matrix.rotate_z(angle.at(time)),
+            Self::Scale(factors) => matrix.scale(factors.at(time)),
+            Self::Matrix(m) => *m * matrix,
+        }
+    }
+
+    /// Undo the step at `time`, None if it cannot be. `matrix_inverse` is
+    /// the inverse of a [`TransformOp::Matrix`], computed once.
+    fn inverse_at(&self, time: f64, matrix_inverse: Option<Mat4>) -> Option<Mat4> {
+        match self {
+            Self::Translate(offset) => Some(Mat4::translation(-offset.at(time))),
+            Self::RotateX(angle) => Some(Mat4::rotation_x(-angle.at(time))),
+            Self::RotateY(angle) => Some(Mat4::rotation_y(-angle.at(time))),
+            Self::RotateZ(angle) => Some(Mat4::rotation_z(-angle.at(time))),
+            Self::Scale(factors) => {
+                let f = factors.at(time);
+                if f.x == 0.0 || f.y == 0.0 || f.z == 0.0 {
+                    return None;
+                }
+                Some(Mat4::scaling(Vec3::new(1.0 / f.x, 1.0 / f.y, 1.0 / f.z)))
+            }
+            Self::Matrix(_) => matrix_inverse,
+        }
+    }
+
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Self::Translate(keys) | Self::Scale(keys) => keys.is_constant(),
+            Self::RotateX(keys) | Self::RotateY(keys) | Self::RotateZ(keys) => keys.is_constant(),
+            Self::Matrix(_) => true,
+        }
+    }
+
+    fn times(&self) -> Vec<f64> {
+        match self {
+            Self::Translate(keys) | Self::Scale(keys) => keys.times().collect(),
+            Self::RotateX(keys) | Self::RotateY(keys) | Self::RotateZ(keys) => {
+                keys.times().collect()
+            }
+            Self::Matrix(_) => Vec::new(),
+        }
+    }
+}
+
+/// Times of the keyframes of all the steps, sorted, at least one
+pub fn keyframe_times(ops: &[TransformOp]) -> Vec<f64> {
+    let mut times: Vec<f64> = ops.iter().flat_map(TransformOp::times).collect();
+    times.sort_by(f64::total_cmp);
+    times.dedup();
+    if times.is_empty() {
+        times.push(0.0);
+    }
+    times
+}
+
+/// Matrix of the steps applied in order at `time`
+pub fn compose(ops: &[TransformOp], time: f64) -> Mat4 {
+    ops.iter()
+        .fold(Mat4::IDENTITY, |matrix, op| op.apply(matrix, time))
+}
+
+/// Instance of an object moved by a transformation changing over time
+pub struct AnimatedTransform {
+    object: ArcHittable,
+    /// Applied in order
+    ops: Vec<TransformOp>,
+    /// Inverse of every `Matrix` step, None for the other ones
+    matrix_inverses: Vec<Option<Mat4>>,
+    bbox: BoundingBox,
+}
+
+impl AnimatedTransform {
+    // Number of times the bounds are evaluated between two keyframes
+    const BOUNDS_STEPS: usize = 32;
+
+    pub fn new(object: ArcHittable, ops: Vec<TransformOp>) -> Self {
+        let times = keyframe_times(&ops);
+
+        // Rotations sweep arcs between keyframes, so sample in between too
+        let object_bbox = object.bounding_box();
+        let mut bbox = BoundingBox::default();
+        let mut add_time = |time: f64| {
+            if let Some(affine) = Affine::new(compose(&ops, time)) {
+                bbox = BoundingBox::from_boxes(bbox.clone(), affine.bounding_box(&object_bbox));
+            }
+        };
+        add_time(times[0]);
+        for pair in times.windows(2) {
+            for step in 1..=Self::BOUNDS_STEPS {
+                add_time(lerp(
+                    pair[0],
+                    pair[1],
+                    step as f64 / Self::BOUNDS_STEPS as f64,
+                ));
+            }
+        }
+
+        let matrix_inverses = ops
+            .iter()
+            .map(|op| match op {
+                TransformOp::Matrix(m) => m.inverse(),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            object,
+            ops,
+            matrix_inverses,
+            bbox,
+        }
+    }
+
+    /// Object to world transformation at `time`
+    pub fn matrix_at(&self, time: f64) -> Mat4 {
+        compose(&self.ops, time)
+    }
+
+    /// Transformation at `time` and its inverse, built from the inverse of
+    /// every step in reverse order rather than inverting the matrix. None
+    /// if it is degenerate at this time, e.g. scaled down to nothing.
+    fn affine_at(&self, time: f64) -> Option<Affine> {
+        let mut matrix = Mat4::IDENTITY;
+        let mut inverse = Mat4::IDENTITY;
+        for (op, matrix_inverse) in self.ops.iter().zip(&self.matrix_inverses) {
+            matrix = op.apply(matrix, time);
+            inverse = inverse * op.inverse_at(time, *matrix_inverse)?;
+        }
+        Some(Affine { matrix, inverse })
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let affine = self.affine_at(ray.time)?;
+        affine.hit(&self.object, ray, interval)
+    }
+
+    fn hit_surface(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let affine = self.affine_at(ray.time)?;
+        affine.hit_surface(&self.object, ray, interval)
+    }
+
+    fn transmittance(&self, ray: &Ray, interval: Interval) -> f64 {
+        match self.affine_at(ray.time) {
+            Some(affine) => affine.transmittance(&self.object, ray, interval),
+            None => 1.0,
+        }
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.bbox.clone()
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
+        match self.affine_at(time) {
+            Some(affine) => affine.pdf_value(&self.object, origin, direction, time),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: Vec3, time: f64) -> Vec3 {
+        match self.affine_at(time) {
+            Some(affine) => affine.random(&self.object, origin, time),
+            None => Vec3::X,
+        }
+    }
+}
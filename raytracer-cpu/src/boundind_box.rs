@@ -1,12 +1,7 @@
 use core::panic;
-use std::{ops, sync::Arc};
+use std::ops;
 
-use crate::{
-    hittables::{ArcHittable, HitRecord, Hittable, HittableList},
-    interval::Interval,
-    ray::Ray,
-    vector::Vec3,
-};
+use crate::{interval::Interval, ray::Ray, vector::Vec3};
 
 // Axis Aligned Bounding Box
 #[derive(Clone, Debug)]
@@ -66,14 +61,40 @@ impl BoundingBox {
         2
     }
 
-    pub fn hit(&self, ray: &Ray, mut ray_t: Interval) -> bool {
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * Vec3::new(
+            self.x.min + self.x.max,
+            self.y.min + self.y.max,
+            self.z.min + self.z.max,
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let (dx, dy, dz) = (self.x.span(), self.y.span(), self.z.span());
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            return 0.0;
+        }
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    pub fn hit(&self, ray: &Ray, ray_t: Interval) -> bool {
+        let inv_direction = Vec3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+        self.hit_inverse(ray.origin, inv_direction, ray_t)
+    }
+
+    /// Same as `hit`, with the inverse of the ray direction precomputed
+    pub fn hit_inverse(&self, origin: Vec3, inv_direction: Vec3, mut ray_t: Interval) -> bool {
         for axis in 0..3 {
             let ax = &self[axis];
-            let adinv = 1.0 / ray.direction[axis];
+            let adinv = inv_direction[axis];
 
             // Bounding box - Ray intersections
-            let t0 = (ax.min - ray.origin[axis]) * adinv;
-            let t1 = (ax.max - ray.origin[axis]) * adinv;
+            let t0 = (ax.min - origin[axis]) * adinv;
+            let t1 = (ax.max - origin[axis]) * adinv;
 
             if t0 < t1 {
                 if t0 > ray_t.min {
@@ -127,76 +148,3 @@ impl ops::Add<BoundingBox> for Vec3 {
         BoundingBox::_new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
     }
 }
-
-// Bounding Volume Hierarhcy
-pub struct BvhNode {
-    left: ArcHittable,
-    right: Option<ArcHittable>,
-    bbox: BoundingBox,
-}
-
-impl BvhNode {
-    pub fn new(objects: &mut [ArcHittable], start: usize, end: usize) -> Self {
-        let bbox = objects[start..end]
-            .iter()
-            .fold(BoundingBox::default(), |bbox, obj| {
-                BoundingBox::from_boxes(bbox, obj.bounding_box())
-            });
-
-        let axis = bbox.longest_axis();
-        let span = end - start;
-
-        let (left, right) = match span {
-            1 => (objects[start].clone(), None),
-            2 => (objects[start].clone(), Some(objects[start + 1].clone())),
-            _ => {
-                objects[start..end].sort_by(|a, b| {
-                    let a_interval = a.bounding_box()[axis];
-                    let b_interval = b.bounding_box()[axis];
-
-                    a_interval.min.partial_cmp(&b_interval.min).unwrap()
-                });
-
-                let mid = start + span / 2;
-                let left = Arc::new(Self::new(objects, start, mid));
-                let right = Arc::new(Self::new(objects, mid, end));
-                (left as ArcHittable, Some(right as ArcHittable))
-            }
-        };
-
-        Self { left, right, bbox }
-    }
-}
-
-impl From<HittableList> for BvhNode {
-    fn from(mut value: HittableList) -> Self {
-        let len = value.objects.len();
-        Self::new(&mut value.objects, 0, len)
-    }
-}
-
-impl Hittable for BvhNode {
-    fn hit(&self, ray: &Ray, mut interval: Interval) -> Option<HitRecord> {
-        if !self.bbox.hit(ray, interval) {
-            return None;
-        }
-
-        let mut record = None;
-        if let Some(left_obj) = self.left.hit(ray, interval) {
-            interval.max = left_obj.distance;
-            record = Some(left_obj);
-        };
-
-        if let Some(node) = &self.right {
-            if let Some(right_obj) = node.hit(ray, interval) {
-                record = Some(right_obj);
-            };
-        }
-
-        record
-    }
-
-    fn bounding_box(&self) -> BoundingBox {
-        self.bbox.clone()
-    }
-}
@@ -0,0 +1,412 @@
+//! Bounding Volume Hierarchy built with the binned surface area heuristic.
+//!
+//! Nodes are stored depth first in a contiguous array and traversed with an
+//! explicit stack, closest child first. The same tree is used for the scene
+//! objects ([`Bvh`]) and for the faces of a triangle mesh.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    boundind_box::BoundingBox,
+    hittables::{ArcHittable, HitRecord, Hittable, HittableList},
+    interval::Interval,
+    ray::Ray,
+    vector::Vec3,
+};
+
+static PRINT_STATS: AtomicBool = AtomicBool::new(false);
+
+/// Print the statistics of every BVH built from now on to stderr
+pub fn print_stats(enabled: bool) {
+    PRINT_STATS.store(enabled, Ordering::Relaxed);
+}
+
+/// Node of a flattened BVH. The left child of an interior node directly
+/// follows it and `offset` is the index of the right child. For leaves
+/// `offset` is the first primitive and `count` the number of primitives.
+#[derive(Debug, Clone)]
+pub struct BvhNode {
+    pub bbox: BoundingBox,
+    pub offset: u32,
+    pub count: u32,
+    /// Split axis of interior nodes
+    pub axis: u8,
+}
+
+impl BvhNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BvhStats {
+    pub primitives: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    /// Expected cost of tracing a random ray, relative to one intersection test
+    pub sah_cost: f64,
+    pub build_time: Duration,
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BVH over {} primitives: {} nodes, {} leaves, depth {}, SAH cost {:.2}, built in {:.1}ms",
+            self.primitives,
+            self.nodes,
+            self.leaves,
+            self.depth,
+            self.sah_cost,
+            self.build_time.as_secs_f64() * 1e3,
+        )
+    }
+}
+
+#[derive(Clone, Default)]
+struct Bin {
+    bbox: BoundingBox,
+    count: usize,
+}
+
+/// Hierarchy over a list of primitives, only their bounding boxes are
+/// needed to build it. Leaves refer to primitives by their position in
+/// `order`, which callers use to reorder their primitives.
+pub struct BvhTree {
+    nodes: Vec<BvhNode>,
+    stats: BvhStats,
+}
+
+impl BvhTree {
+    const BINS: usize = 12;
+    const MAX_LEAF_SIZE: usize = 4;
+    // Leaves are forced past this depth so traversal fits its stack
+    const MAX_DEPTH: usize = 64;
+    // Costs relative to a primitive intersection
+    const TRAVERSAL_COST: f64 = 0.125;
+    const INTERSECTION_COST: f64 = 1.0;
+
+    /// Build the tree, returns it along with the order the primitives must
+    /// be stored in
+    pub fn build(bboxes: &[BoundingBox]) -> (Self, Vec<usize>) {
+        let start = Instant::now();
+        let centroids: Vec<Vec3> = bboxes.iter().map(BoundingBox::centroid).collect();
+        let mut order: Vec<usize> = (0..bboxes.len()).collect();
+
+        let mut tree = Self {
+            nodes: Vec::with_capacity(2 * bboxes.len() / Self::MAX_LEAF_SIZE + 1),
+            stats: BvhStats {
+                primitives: bboxes.len(),
+                ..Default::default()
+            },
+        };
+        if !bboxes.is_empty() {
+            tree.build_node(bboxes, &centroids, &mut order, 0, 1);
+        }
+
+        tree.stats.nodes = tree.nodes.len();
+        tree.stats.sah_cost = tree.sah_cost();
+        tree.stats.build_time = start.elapsed();
+        if PRINT_STATS.load(Ordering::Relaxed) {
+            eprintln!("{}", tree.stats);
+        }
+
+        (tree, order)
+    }
+
+    pub fn nodes(&self) -> &[BvhNode] {
+        &self.nodes
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        self.nodes
+            .first()
+            .map_or(BoundingBox::default(), |node| node.bbox.clone())
+    }
+
+    // `first` is the position of `order` in the full list of primitives
+    fn build_node(
+        &mut self,
+        bboxes: &[BoundingBox],
+        centroids: &[Vec3],
+        order: &mut [usize],
+        first: usize,
+        depth: usize,
+    ) {
+        self.stats.depth = self.stats.depth.max(depth);
+        let bbox = order.iter().fold(BoundingBox::default(), |bbox, &i| {
+            BoundingBox::from_boxes(bbox, bboxes[i].clone())
+        });
+
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bbox: bbox.clone(),
+            offset: first as u32,
+            count: order.len() as u32,
+            axis: 0,
+        });
+
+        if order.len() == 1 || depth >= Self::MAX_DEPTH {
+            self.stats.leaves += 1;
+            return;
+        }
+
+        let centroid_bounds = order.iter().fold(BoundingBox::default(), |bounds, &i| {
+            BoundingBox::from_boxes(
+                bounds,
+                BoundingBox::from_extrema(centroids[i], centroids[i]),
+            )
+        });
+        let split = Self::find_split(bboxes, centroids, order, &bbox, &centroid_bounds);
+
+        let (axis, mid) = match split {
+            Some((axis, bin, cost)) => {
+                let leaf_cost = Self::INTERSECTION_COST * order.len() as f64;
+                if cost >= leaf_cost && order.len() <= Self::MAX_LEAF_SIZE {
+                    self.stats.leaves += 1;
+                    return;
+                }
+
+                let interval = centroid_bounds[axis];
+                let mid = partition(order, |&i| {
+                    Self::bin_index(centroids[i][axis], interval) <= bin
+                });
+                (axis, mid)
+            }
+            // Every centroid is at the same place, split in the middle
+            None if order.len() <= Self::MAX_LEAF_SIZE => {
+                self.stats.leaves += 1;
+                return;
+            }
+            None => (centroid_bounds.longest_axis(), order.len() / 2),
+        };
+
+        self.nodes[index].count = 0;
+        self.nodes[index].axis = axis as u8;
+
+        let (left, right) = order.split_at_mut(mid);
+        self.build_node(bboxes, centroids, left, first, depth + 1);
+        self.nodes[index].offset = self.nodes.len() as u32;
+        self.build_node(bboxes, centroids, right, first + mid, depth + 1);
+    }
+
+    fn bin_index(centroid: f64, interval: Interval) -> usize {
+        let relative = (centroid - interval.min) / interval.span();
+        ((relative * Self::BINS as f64) as usize).min(Self::BINS - 1)
+    }
+
+    /// Cheapest split over the bins of every axis, as (axis, last bin on the
+    /// left, cost). Returns None when the centroids cannot be separated.
+    fn find_split(
+        bboxes: &[BoundingBox],
+        centroids: &[Vec3],
+        order: &[usize],
+        bbox: &BoundingBox,
+        centroid_bounds: &BoundingBox,
+    ) -> Option<(usize, usize, f64)> {
+        let mut best: Option<(usize, usize, f64)> = None;
+
+        for axis in 0..3 {
+            let interval = centroid_bounds[axis];
+            if interval.span() <= 0.0 {
+                continue;
+            }
+
+            let mut bins = vec![Bin::default(); Self::BINS];
+            for &i in order {
+                let bin = &mut bins[Self::bin_index(centroids[i][axis], interval)];
+                bin.bbox = BoundingBox::from_boxes(bin.bbox.clone(), bboxes[i].clone());
+                bin.count += 1;
+            }
+
+            // Sweep from the right to get the area and count of every suffix
+            let mut right_area = [0.0; Self::BINS];
+            let mut right_count = [0; Self::BINS];
+            let mut accumulated = Bin::default();
+            for bin in (1..Self::BINS).rev() {
+                accumulated.bbox =
+                    BoundingBox::from_boxes(accumulated.bbox, bins[bin].bbox.clone());
+                accumulated.count += bins[bin].count;
+                right_area[bin] = accumulated.bbox.surface_area();
+                right_count[bin] = accumulated.count;
+            }
+
+            let mut left = Bin::default();
+            for bin in 0..Self::BINS - 1 {
+                left.bbox = BoundingBox::from_boxes(left.bbox, bins[bin].bbox.clone());
+                left.count += bins[bin].count;
+                let (count_right, area_right) = (right_count[bin + 1], right_area[bin + 1]);
+                if left.count == 0 || count_right == 0 {
+                    continue;
+                }
+
+                let cost = Self::TRAVERSAL_COST
+                    + Self::INTERSECTION_COST
+                        * (left.count as f64 * left.bbox.surface_area()
+                            + count_right as f64 * area_right)
+                        / bbox.surface_area();
+                if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best = Some((axis, bin, cost));
+                }
+            }
+        }
+
+        best
+    }
+
+    fn sah_cost(&self) -> f64 {
+        let Some(root) = self.nodes.first() else {
+            return 0.0;
+        };
+        let root_area = root.bbox.surface_area();
+        if root_area <= 0.0 {
+            return 0.0;
+        }
+
+        self.nodes
+            .iter()
+            .map(|node| {
+                let probability = node.bbox.surface_area() / root_area;
+                if node.is_leaf() {
+                    probability * Self::INTERSECTION_COST * node.count as f64
+                } else {
+                    probability * Self::TRAVERSAL_COST
+                }
+            })
+            .sum()
+    }
+
+    /// Find the closest hit along `ray`. `hit_primitive` intersects the
+    /// primitive stored at the given position within an interval.
+    pub fn hit<F>(
+        &self,
+        ray: &Ray,
+        mut interval: Interval,
+        mut hit_primitive: F,
+    ) -> Option<HitRecord>
+    where
+        F: FnMut(usize, Interval) -> Option<HitRecord>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inv_direction = Vec3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+        let mut record = None;
+        let mut stack = [0u32; Self::MAX_DEPTH + 1];
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let index = stack[len] as usize;
+            let node = &self.nodes[index];
+            if !node.bbox.hit_inverse(ray.origin, inv_direction, interval) {
+                continue;
+            }
+
+            if node.is_leaf() {
+                let start = node.offset as usize;
+                for i in start..start + node.count as usize {
+                    if let Some(r) = hit_primitive(i, interval) {
+                        interval.max = r.distance;
+                        record = Some(r);
+                    }
+                }
+                continue;
+            }
+
+            // Push the far child first so the near one is visited first
+            let (left, right) = (index as u32 + 1, node.offset);
+            let (near, far) = if inv_direction[node.axis as usize] < 0.0 {
+                (right, left)
+            } else {
+                (left, right)
+            };
+            stack[len] = far;
+            stack[len + 1] = near;
+            len += 2;
+        }
+
+        record
+    }
+}
+
+/// Move the elements matching `predicate` to the front, returns their count
+fn partition<T>(items: &mut [T], mut predicate: impl FnMut(&T) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+/// BVH over scene objects
+pub struct Bvh {
+    objects: Vec<ArcHittable>,
+    tree: BvhTree,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<ArcHittable>) -> Self {
+        let bboxes: Vec<_> = objects.iter().map(|obj| obj.bounding_box()).collect();
+        let (tree, order) = BvhTree::build(&bboxes);
+        let objects = order.into_iter().map(|i| objects[i].clone()).collect();
+
+        Self { objects, tree }
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        self.tree.stats()
+    }
+}
+
+impl From<HittableList> for Bvh {
+    fn from(value: HittableList) -> Self {
+        Self::new(value.objects)
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.tree.hit(ray, interval, |i, interval| {
+            self.objects[i].hit(ray, interval)
+        })
+    }
+
+    fn hit_surface(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.tree.hit(ray, interval, |i, interval| {
+            self.objects[i].hit_surface(ray, interval)
+        })
+    }
+
+    // Nothing is ever hit, so every object along the ray is visited
+    fn transmittance(&self, ray: &Ray, interval: Interval) -> f64 {
+        let mut transmittance = 1.0;
+        self.tree.hit(ray, interval, |i, interval| {
+            transmittance *= self.objects[i].transmittance(ray, interval);
+            None
+        });
+        transmittance
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.tree.bounding_box()
+    }
+}
@@ -1,19 +1,47 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Write},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
 };
 
 use rayon::prelude::*;
 
 use crate::{
-    color::{color_to_string, Color},
-    hittables::Hittable,
+    color::Color,
+    environment::ArcEnvironment,
+    film::{Features, Film},
+    filter::Filter,
+    hittables::{HitRecord, Hittable, HittableList},
     interval::Interval,
+    material::Scatter,
+    pdf::{HittablePdf, Pdf},
+    progress::{Progress, ProgressReporter, Silent},
     random,
     ray::Ray,
-    vector::{cross, unit_vector, Vec3},
+    sampler::{ArcSampler, SamplerKind},
+    utils::lerp,
+    vector::{cross, dot, unit_vector, Vec3},
 };
 
+/// Side length in pixels of the square tiles the image is split into
+const TILE_SIZE: usize = 32;
+
+/// Sampler dimensions of a camera ray: position in the pixel (0, 1), on the
+/// lens (2, 3) and time (4)
+const CAMERA_DIMENSIONS: u32 = 6;
+/// Sampler dimensions of every bounce: light sampling first, then the
+/// scattered direction, then Russian roulette
+const LIGHT_DIMENSIONS: u32 = 4;
+const SCATTER_DIMENSIONS: u32 = 4;
+const BOUNCE_DIMENSIONS: u32 = 10;
+
+/// Block of pixels rendered as a single unit of work
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
 #[derive(Debug)]
 pub struct CameraConfig {
     pub aspect_ratio: f64,
@@ -22,6 +50,8 @@ pub struct CameraConfig {
     pub samples: i32,
     /// Maximum number of ray bounces
     pub max_depth: i32,
+    /// Number of bounces before paths can be terminated by Russian roulette
+    pub rr_min_depth: i32,
     /// Vertical viewing angle (field of view)
     pub vfov: f64,
     /// Point the camera is looking from
@@ -36,6 +66,33 @@ pub struct CameraConfig {
     pub focus_dist: f64,
     /// Scene background color
     pub background: Color,
+    /// Image lighting the scene from every direction, replaces `background`
+    pub environment: Option<ArcEnvironment>,
+    /// Seed of the random streams used for sampling
+    pub seed: u64,
+    /// Pattern of the samples of every pixel
+    pub sampler: SamplerKind,
+    /// Reconstruction filter spreading every sample over the pixels around it
+    pub filter: Filter,
+    /// Stop sampling a pixel once the standard error of its luminance falls
+    /// below this fraction of it, `samples` is then the maximum. Without it
+    /// every pixel gets `samples` samples.
+    pub adaptive_threshold: Option<f64>,
+    /// Samples every pixel gets before its error is estimated, then added
+    /// at every pass over the pixels that need more
+    pub min_samples: i32,
+    /// Stop adding samples once the render took this long, every pixel
+    /// still gets `min_samples` samples
+    pub time_limit: Option<Duration>,
+    /// Scene time of the image in seconds, e.g. the start of a frame
+    pub time: f64,
+    /// The shutter is open from `shutter_open` to `shutter_close` frames
+    /// after `time`, a frame lasting `1 / fps` seconds. Objects moving in
+    /// between are blurred, the default is a 180 degree shutter.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    /// Frame rate of animations
+    pub fps: f64,
 }
 
 impl Default for CameraConfig {
@@ -45,6 +102,7 @@ impl Default for CameraConfig {
             image_width: 500,
             samples: 10,
             max_depth: 10,
+            rr_min_depth: 3,
             vfov: 90.0,
             look_from: Vec3::default(),
             look_at: Vec3::default(),
@@ -52,6 +110,17 @@ impl Default for CameraConfig {
             defocus_angle: 0.0,
             focus_dist: 10.0,
             background: Color::new(0.7, 0.8, 1.0),
+            environment: None,
+            seed: 0,
+            sampler: SamplerKind::default(),
+            filter: Filter::default(),
+            adaptive_threshold: None,
+            min_samples: 16,
+            time_limit: None,
+            time: 0.0,
+            shutter_open: 0.0,
+            shutter_close: 0.5,
+            fps: 24.0,
         }
     }
 }
@@ -66,10 +135,23 @@ pub struct Camera {
     pixel_delta_v: Vec3,
     samples: i32,
     max_depth: i32,
+    rr_min_depth: i32,
     defocus_angle: f64,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
     background: Color,
+    environment: Option<ArcEnvironment>,
+    seed: u64,
+    sampler: ArcSampler,
+    filter: Filter,
+    adaptive_threshold: Option<f64>,
+    min_samples: i32,
+    time_limit: Option<Duration>,
+    shutter_open: f64,
+    shutter_close: f64,
+    /// Angle covered by a pixel, camera rays are cones this wide for
+    /// texture filtering
+    pixel_spread: f64,
 }
 
 impl Camera {
@@ -111,8 +193,21 @@ impl Camera {
             image_width: config.image_width,
             samples: config.samples,
             max_depth: config.max_depth,
+            rr_min_depth: config.rr_min_depth,
             defocus_angle: config.defocus_angle,
             background: config.background,
+            environment: config.environment,
+            seed: config.seed,
+            sampler: config
+                .sampler
+                .build(config.samples.max(1) as u32, config.seed),
+            filter: config.filter,
+            adaptive_threshold: config.adaptive_threshold,
+            min_samples: config.min_samples.clamp(1, config.samples.max(1)),
+            time_limit: config.time_limit,
+            shutter_open: config.time + config.shutter_open / config.fps,
+            shutter_close: config.time + config.shutter_close / config.fps,
+            pixel_spread: 2.0 * h / image_height as f64,
             image_height,
             center,
             pixel_00,
@@ -123,49 +218,191 @@ impl Camera {
         }
     }
 
-    pub fn render(&self, world: impl Hittable + Send + Sync) -> std::io::Result<()> {
-        let file = File::create("out.ppm")?;
-        let mut writer = BufWriter::new(file);
+    /// Render `world` into an in-memory film holding the accumulated samples.
+    /// `lights` are the emissive objects sampled explicitly at every bounce.
+    pub fn render(&self, world: impl Hittable + Send + Sync, lights: &HittableList) -> Film {
+        self.render_with_progress(world, lights, &Silent)
+    }
 
-        let header = format!("P3\n{} {}\n255\n", self.image_width, self.image_height);
-        let _ = writer.write(header.as_bytes())?;
+    /// Same as [`Camera::render`], reporting the progress after every tile
+    pub fn render_with_progress(
+        &self,
+        world: impl Hittable + Send + Sync,
+        lights: &HittableList,
+        reporter: &dyn ProgressReporter,
+    ) -> Film {
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+        let mut film = Film::new(width, height);
 
-        let mut stdout = std::io::stdout();
-        for j in 0..self.image_height {
-            print!("\rScanlines remaining: {:>3}", self.image_height - j);
-            stdout.flush()?;
+        // Upper bound, adaptive sampling may stop earlier
+        let samples_total = (width * height) as u64 * self.samples as u64;
+        let samples_done = AtomicU64::new(0);
+        let start = Instant::now();
+
+        let tiles: Vec<Tile> = (0..height)
+            .step_by(TILE_SIZE)
+            .flat_map(|y| {
+                (0..width).step_by(TILE_SIZE).map(move |x| Tile {
+                    x,
+                    y,
+                    width: TILE_SIZE.min(width - x),
+                    height: TILE_SIZE.min(height - y),
+                })
+            })
+            .collect();
+
+        // Every pixel gets the same first pass, the following ones only go
+        // over the pixels that still need samples
+        let first_pass = if self.adaptive_threshold.is_some() || self.time_limit.is_some() {
+            self.min_samples
+        } else {
+            self.samples
+        };
+        let mut budget = vec![first_pass as u32; width * height];
 
-            (0..self.image_width)
-                .into_par_iter()
-                .map(|i| {
-                    let pixel_color = (0..self.samples)
-                        // .into_par_iter()
-                        .map(|_| self.get_color(self.get_ray(i, j), &world, self.max_depth))
-                        .sum();
+        for pass in 0.. {
+            let tile_films: Vec<Option<Film>> = tiles
+                .par_iter()
+                .map(|tile| {
+                    if pass > 0 && self.out_of_time(start) {
+                        return None;
+                    }
+                    let tile_film = self.render_tile(tile, &film, &budget, &world, lights);
 
-                    color_to_string(pixel_color, self.samples)
+                    let done: u64 = tile_film.pixels().iter().map(|p| p.samples as u64).sum();
+                    reporter.update(&Progress {
+                        samples_done: samples_done.fetch_add(done, Ordering::Relaxed) + done,
+                        samples_total,
+                        elapsed: start.elapsed(),
+                    });
+                    Some(tile_film)
                 })
-                .collect::<Vec<String>>()
-                .into_iter()
-                // TODO: fix this unwrap?
-                .for_each(|color| {
-                    let _ = writer.write(color.as_bytes()).unwrap();
-                });
-
-            writer.flush()?;
+                .collect();
+
+            // Tiles overlap by the margin of the filter, adding them in the
+            // same order whatever thread finished first keeps the sums of
+            // the shared pixels identical from one render to the next
+            let mut pass_film = Film::new(width, height);
+            let margin = self.filter.margin() as isize;
+            for (tile, tile_film) in tiles.iter().zip(tile_films) {
+                if let Some(tile_film) = tile_film {
+                    pass_film.merge_at(
+                        &tile_film,
+                        tile.x as isize - margin,
+                        tile.y as isize - margin,
+                    );
+                }
+            }
+            film.merge(&pass_film);
+
+            if self.out_of_time(start) {
+                break;
+            }
+            budget = self.budget(&film);
+            if budget.iter().all(|&samples| samples == 0) {
+                break;
+            }
         }
 
-        println!("\rDone!                   ");
-        Ok(())
+        // The render is complete even when it took fewer samples than allowed
+        let samples_done = samples_done.into_inner();
+        reporter.finish(&Progress {
+            samples_done,
+            samples_total: samples_done,
+            elapsed: start.elapsed(),
+        });
+
+        film
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let ray_origin = self.center + self.defocus_angle * self.defocus_disk_sample();
+    fn out_of_time(&self, start: Instant) -> bool {
+        self.time_limit
+            .is_some_and(|limit| start.elapsed() >= limit)
+    }
+
+    /// Number of samples to add to every pixel at the next pass: none once
+    /// it has `samples` or its error is below the threshold
+    fn budget(&self, film: &Film) -> Vec<u32> {
+        film.pixels()
+            .iter()
+            .map(|pixel| {
+                let converged = self
+                    .adaptive_threshold
+                    .is_some_and(|threshold| pixel.relative_error() <= threshold);
+                let remaining = (self.samples as u32).saturating_sub(pixel.samples);
+                if converged {
+                    0
+                } else {
+                    remaining.min(self.min_samples as u32)
+                }
+            })
+            .collect()
+    }
+
+    /// Take `budget` more samples in every pixel of `tile`, continuing the
+    /// sample indices of `film`. The returned film is larger than the tile
+    /// by the margin of the filter on every side.
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        film: &Film,
+        budget: &[u32],
+        world: &impl Hittable,
+        lights: &HittableList,
+    ) -> Film {
+        let margin = self.filter.margin();
+        let mut tile_film = Film::new(tile.width + 2 * margin, tile.height + 2 * margin);
+
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let i = (tile.x + x) as i32;
+                let j = (tile.y + y) as i32;
+                let pixel = (j * self.image_width + i) as u64;
+                let first = film.pixel(i as usize, j as usize).samples;
+
+                for sample in first..first + budget[pixel as usize] {
+                    random::seed(random::derive(self.seed, pixel, sample as u64));
+                    random::start_sample(&self.sampler, (i as u32, j as u32), sample);
+                    let offset = self.pixel_offset();
+                    let mut features = Features::default();
+                    let ray = self.get_ray(i, j, offset);
+                    let color = self.get_color(ray, world, lights, &mut features);
+                    random::end_sample();
+                    tile_film.add_sample(
+                        x + margin,
+                        y + margin,
+                        offset,
+                        color,
+                        features,
+                        &self.filter,
+                    );
+                }
+            }
+        }
+
+        tile_film
+    }
+
+    /// Position of a sample inside of its pixel, from the top left corner
+    fn pixel_offset(&self) -> (f64, f64) {
+        random::dimensions(0, 2);
+        (random::float(), random::float())
+    }
 
+    /// Camera ray through the point at `offset` inside of pixel (i, j)
+    fn get_ray(&self, i: i32, j: i32, offset: (f64, f64)) -> Ray {
         let pixel_center = self.pixel_00 + (i * self.pixel_delta_u) + (j * self.pixel_delta_v);
-        let ray_target = pixel_center + self.pixel_sample_square();
+        let ray_target = pixel_center
+            + ((offset.0 - 0.5) * self.pixel_delta_u)
+            + ((offset.1 - 0.5) * self.pixel_delta_v);
+
+        random::dimensions(2, 2);
+        let ray_origin = self.center + self.defocus_angle * self.defocus_disk_sample();
         let ray_direction = ray_target - ray_origin;
-        let ray_time = random::float();
+
+        random::dimensions(4, CAMERA_DIMENSIONS - 4);
+        let ray_time = lerp(self.shutter_open, self.shutter_close, random::float());
 
         Ray::new(ray_origin, ray_direction, ray_time)
     }
@@ -179,32 +416,214 @@ impl Camera {
         (vec.x * self.defocus_disk_u) + (vec.y * self.defocus_disk_v)
     }
 
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + random::float();
-        let py = -0.5 + random::float();
-        (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
-    }
+    /// Trace a path starting with `ray` and return the radiance it carries
+    /// back to the camera, `features` are set from its first bounce
+    fn get_color(
+        &self,
+        mut ray: Ray,
+        world: &impl Hittable,
+        lights: &HittableList,
+        features: &mut Features,
+    ) -> Color {
+        let mut color = Color::BLACK;
+        // Fraction of the light found further along the path that reaches the camera
+        let mut throughput = Color::WHITE;
+        // Density `ray` was sampled with, None for camera rays and specular bounces
+        let mut ray_pdf = None;
+        // Width of the ray cone at the origin of `ray`, it keeps the spread
+        // of a pixel at every bounce
+        let mut cone_width = 0.0;
 
-    // TODO: get rid of recursion?
-    fn get_color(&self, ray: Ray, world: &impl Hittable, depth: i32) -> Color {
-        if depth <= 0 {
-            return Color::BLACK;
+        for depth in 0..self.max_depth {
+            let dimension = CAMERA_DIMENSIONS + depth as u32 * BOUNCE_DIMENSIONS;
+            let Some(mut hit_obj) = world.hit(&ray, Interval::positive()) else {
+                let escaped = self.escaped(&ray, ray_pdf, lights);
+                if depth == 0 {
+                    features.albedo = clamp_color(escaped);
+                }
+                return color + throughput * escaped;
+            };
+            if depth == 0 {
+                features.normal = hit_obj.normal;
+                features.depth = hit_obj.distance * ray.direction.len();
+            }
+            cone_width += self.pixel_spread * hit_obj.distance * ray.direction.len();
+            // The cone is stretched along the surface at grazing angles
+            let cos_theta = dot(unit_vector(ray.direction), hit_obj.normal).abs();
+            hit_obj.footprint = cone_width * hit_obj.uv_density / cos_theta.max(0.01);
+
+            // Lights hit by chance were also reachable through light sampling
+            // at the previous bounce, weight both strategies
+            let emission = hit_obj.material.emit(hit_obj.uv, hit_obj.point);
+            let weight = ray_pdf.map_or(1.0, |pdf| {
+                power_heuristic(pdf, self.light_pdf_value(&ray, lights))
+            });
+            color += throughput * emission * weight;
+
+            random::dimensions(dimension + LIGHT_DIMENSIONS, SCATTER_DIMENSIONS);
+            let Some(scatter) = hit_obj.material.scatter(&ray, &hit_obj) else {
+                if depth == 0 {
+                    features.albedo = clamp_color(emission);
+                }
+                return color;
+            };
+
+            let ray_scattered = match scatter {
+                Scatter::Specular {
+                    ray: ray_scattered,
+                    attenuation,
+                } => {
+                    throughput = throughput * attenuation;
+                    ray_pdf = None;
+                    ray_scattered
+                }
+                Scatter::Pdf(pdf) => {
+                    random::dimensions(dimension, LIGHT_DIMENSIONS);
+                    color += throughput
+                        * self.sample_lights(&ray, &hit_obj, pdf.as_ref(), world, lights);
+
+                    random::dimensions(dimension + LIGHT_DIMENSIONS, SCATTER_DIMENSIONS);
+                    let ray_scattered = Ray::new(hit_obj.point, pdf.generate(), ray.time);
+                    let pdf_value = pdf.value(ray_scattered.direction);
+                    if pdf_value <= 0.0 {
+                        return color;
+                    }
+
+                    let bsdf = hit_obj.material.eval(&ray, &hit_obj, &ray_scattered);
+                    throughput = throughput * bsdf / pdf_value;
+                    ray_pdf = Some(pdf_value);
+                    ray_scattered
+                }
+            };
+            // The weight of the first scattered ray averages to the albedo
+            if depth == 0 {
+                features.albedo = clamp_color(throughput);
+            }
+
+            // Russian roulette: randomly stop paths that can only carry little
+            // light, boosting the survivors so the estimate stays unbiased
+            if depth >= self.rr_min_depth {
+                random::dimensions(dimension + LIGHT_DIMENSIONS + SCATTER_DIMENSIONS, 1);
+                let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+                if random::float() >= survival {
+                    return color;
+                }
+                throughput /= survival;
+            }
+
+            ray = ray_scattered;
         }
 
-        let Some(hit_obj) = world.hit(&ray, Interval::positive()) else {
+        color
+    }
+
+    /// Radiance carried by `ray` leaving the scene, sampled with density
+    /// `ray_pdf` (None if it could not have been sampled towards a light)
+    fn escaped(&self, ray: &Ray, ray_pdf: Option<f64>, lights: &HittableList) -> Color {
+        let Some(environment) = &self.environment else {
             return self.background;
         };
 
-        let color_from_emission = hit_obj.material.emit(hit_obj.uv, hit_obj.point);
-        match hit_obj.material.scatter(&ray, &hit_obj) {
-            Some((ray_scattered, attenuation)) => {
-                let color_from_scatter =
-                    attenuation * self.get_color(ray_scattered, world, depth - 1);
-                color_from_emission + color_from_scatter
-            }
-            None => color_from_emission,
+        // The environment is also sampled as a light, weight both strategies
+        let weight = ray_pdf.map_or(1.0, |pdf| {
+            power_heuristic(pdf, self.light_pdf_value(ray, lights))
+        });
+        environment.radiance(ray.direction) * weight
+    }
+
+    /// Probability of sampling the environment rather than the lights
+    fn environment_probability(&self, lights: &HittableList) -> f64 {
+        match (&self.environment, lights.objects.is_empty()) {
+            (None, _) => 0.0,
+            (Some(_), true) => 1.0,
+            (Some(_), false) => 0.5,
         }
     }
+
+    /// Density of sampling the direction of `ray` with `sample_lights`, a
+    /// mixture of the environment and the lights
+    fn light_pdf_value(&self, ray: &Ray, lights: &HittableList) -> f64 {
+        let environment_probability = self.environment_probability(lights);
+        let mut pdf = 0.0;
+        if let Some(environment) = &self.environment {
+            pdf += environment_probability * environment.pdf_value(ray.direction);
+        }
+        if !lights.objects.is_empty() {
+            pdf += (1.0 - environment_probability)
+                * lights.pdf_value(ray.origin, ray.direction, ray.time);
+        }
+        pdf
+    }
+
+    /// Next event estimation: pick a direction towards the lights and return
+    /// the light scattered back along `ray` from there, weighted against the
+    /// material sampling the same direction
+    fn sample_lights(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        material_pdf: &dyn Pdf,
+        world: &impl Hittable,
+        lights: &HittableList,
+    ) -> Color {
+        let environment_probability = self.environment_probability(lights);
+        let light_pdf = HittablePdf::new(lights, record.point, ray.time);
+        // Always drawn, so the light sample takes the same dimensions
+        let choice = random::float();
+        let direction = match &self.environment {
+            Some(environment) if choice < environment_probability => environment.random(),
+            _ if lights.objects.is_empty() => return Color::BLACK,
+            _ => light_pdf.generate(),
+        };
+
+        let to_light = Ray::new(record.point, direction, ray.time);
+        let light_pdf_value = self.light_pdf_value(&to_light, lights);
+        if light_pdf_value <= 0.0 {
+            return Color::BLACK;
+        }
+
+        let bsdf = record.material.eval(ray, record, &to_light);
+        if bsdf.x.max(bsdf.y).max(bsdf.z) <= 0.0 {
+            return Color::BLACK;
+        }
+
+        // Whatever surface is hit first either is the light or occludes it,
+        // the media in between dim it
+        let light_hit = world.hit_surface(&to_light, Interval::positive());
+        let emission = match (&light_hit, &self.environment) {
+            (Some(light_hit), _) => light_hit.material.emit(light_hit.uv, light_hit.point),
+            (None, Some(environment)) => environment.radiance(direction),
+            (None, None) => return Color::BLACK,
+        };
+        if emission.x.max(emission.y).max(emission.z) <= 0.0 {
+            return Color::BLACK;
+        }
+        let distance = light_hit.map_or(f64::INFINITY, |hit| hit.distance);
+        let transmittance =
+            world.transmittance(&to_light, Interval::new(Interval::positive().min, distance));
+        let weight = power_heuristic(light_pdf_value, material_pdf.value(to_light.direction));
+
+        bsdf * emission * (transmittance * weight / light_pdf_value)
+    }
+}
+
+/// Multiple importance sampling weight of a strategy sampling with density
+/// `pdf` against another one sampling with `other_pdf`
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b <= 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+/// Color with every component in [0, 1]
+fn clamp_color(color: Color) -> Color {
+    Color::new(
+        color.x.clamp(0.0, 1.0),
+        color.y.clamp(0.0, 1.0),
+        color.z.clamp(0.0, 1.0),
+    )
 }
 
 impl Default for Camera {
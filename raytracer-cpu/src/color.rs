@@ -17,23 +17,29 @@ impl Color {
     pub fn from_f32_slice(slice: &[f32]) -> Self {
         Self::new(slice[0].into(), slice[1].into(), slice[2].into())
     }
+
+    /// Perceived brightness of a linear color (Rec. 709 primaries)
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
 }
 
 fn linear_to_gamma(linear_component: f64) -> f64 {
     linear_component.sqrt()
 }
 
-pub fn color_to_string(color: Color, samples: i32) -> String {
-    let scale = 1.0 / samples as f64;
+/// Gamma correct a linear color and quantize it to 8 bits per channel
+pub fn to_rgb8(color: Color) -> [u8; 3] {
     let intensity = Interval::new(0.0, 0.999);
+    let quantize = |c: f64| (intensity.clamp(linear_to_gamma(c)) * 256.0) as u8;
 
-    let r = linear_to_gamma(color.x * scale);
-    let g = linear_to_gamma(color.y * scale);
-    let b = linear_to_gamma(color.z * scale);
+    [quantize(color.x), quantize(color.y), quantize(color.z)]
+}
 
-    let r = (intensity.clamp(r) * 255.999) as i32;
-    let g = (intensity.clamp(g) * 255.999) as i32;
-    let b = (intensity.clamp(b) * 255.999) as i32;
+/// Gamma correct a linear color and quantize it to 16 bits per channel
+pub fn to_rgb16(color: Color) -> [u16; 3] {
+    let intensity = Interval::new(0.0, 1.0);
+    let quantize = |c: f64| (intensity.clamp(linear_to_gamma(c)) * 65535.0).round() as u16;
 
-    format!("{r} {g} {b}\n")
+    [quantize(color.x), quantize(color.y), quantize(color.z)]
 }
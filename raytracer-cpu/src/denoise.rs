@@ -0,0 +1,232 @@
+//! Denoiser run on a rendered film.
+//!
+//! Edge-avoiding à-trous wavelet filter (Dammertz et al., 2010) guided by
+//! the albedo, normal and depth of the first surface seen in every pixel
+//! and by the variance of its samples (Schied et al., 2017). Every
+//! iteration averages pixels with a 5×5 kernel whose taps are twice as far
+//! apart as in the previous one, pixels only being mixed with neighbours
+//! seeing similar surfaces and having a close color given the noise.
+//!
+//! The albedo is divided out before filtering, so only the lighting is
+//! smoothed and textures stay sharp.
+
+use rayon::prelude::*;
+
+use crate::{
+    color::Color,
+    film::{Features, Film},
+    vector::{dot, unit_vector, Vec3},
+};
+
+/// B3 spline, the 1D kernel of every iteration
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Albedo under which a channel is not divided out, it would blow up noise
+const DARK_ALBEDO: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Denoiser {
+    /// Number of wavelet iterations, the last one reaches 2^(n + 1)
+    /// pixels away
+    pub iterations: u32,
+    /// Luminance differences are allowed this many standard deviations of
+    /// the noise
+    pub sigma_color: f64,
+    /// Exponent of the cosine between normals, higher keeps sharper edges
+    /// between faces
+    pub sigma_normal: f64,
+    /// Relative difference of depth allowed per pixel of distance
+    pub sigma_depth: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 4.0,
+            sigma_normal: 128.0,
+            sigma_depth: 0.1,
+        }
+    }
+}
+
+/// State of a pixel between iterations
+#[derive(Clone, Copy)]
+struct Texel {
+    /// Radiance divided by the albedo
+    irradiance: Color,
+    /// Variance of the luminance of `irradiance`
+    variance: f64,
+}
+
+impl Denoiser {
+    /// Denoised linear radiance of every pixel of `film`, row by row
+    pub fn denoise(&self, film: &Film) -> Vec<Color> {
+        let features: Vec<Features> = film.pixels().iter().map(|p| p.features()).collect();
+        let albedo: Vec<Color> = features.iter().map(|f| demodulation(f.albedo)).collect();
+        let normals: Vec<Vec3> = features
+            .iter()
+            .map(|f| {
+                if f.normal.len_squared() > 0.0 {
+                    unit_vector(f.normal)
+                } else {
+                    Vec3::default()
+                }
+            })
+            .collect();
+
+        let mut texels: Vec<Texel> = film
+            .pixels()
+            .iter()
+            .zip(&albedo)
+            .map(|(pixel, albedo)| {
+                let irradiance = pixel.color() / *albedo;
+                // Variance of the mean, a pixel with too few samples to tell
+                // accepts any neighbour
+                let variance = pixel.variance() / pixel.samples as f64;
+                let variance = if variance.is_finite() {
+                    variance / albedo.luminance().powi(2)
+                } else {
+                    f64::MAX
+                };
+                Texel {
+                    irradiance,
+                    variance,
+                }
+            })
+            .collect();
+
+        let guide = Guide {
+            width: film.width,
+            height: film.height,
+            normals: &normals,
+            depths: &features.iter().map(|f| f.depth).collect::<Vec<_>>(),
+        };
+        for iteration in 0..self.iterations {
+            texels = self.iterate(&guide, &texels, 1 << iteration);
+        }
+
+        texels
+            .iter()
+            .zip(&albedo)
+            .map(|(texel, albedo)| texel.irradiance * *albedo)
+            .collect()
+    }
+
+    /// One à-trous pass with taps `step` pixels apart
+    fn iterate(&self, guide: &Guide, texels: &[Texel], step: usize) -> Vec<Texel> {
+        let (width, height) = (guide.width, guide.height);
+        let mut filtered = texels.to_vec();
+
+        filtered
+            .par_chunks_mut(width.max(1))
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, out) in row.iter_mut().enumerate() {
+                    let p = y * width + x;
+                    let center = texels[p];
+                    let luminance = center.irradiance.luminance();
+                    // The variance of a single pixel is noisy too, pixels
+                    // whose samples missed the rare bright paths would keep
+                    // them. The constant keeps converged pixels from
+                    // rejecting every neighbour.
+                    let variance = blurred_variance(texels, width, height, x, y);
+                    let color_scale = self.sigma_color * variance.sqrt() + 1e-4;
+
+                    let mut sum = Color::BLACK;
+                    let mut variance = 0.0;
+                    let mut weights = 0.0;
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        let qy = y as isize + (j as isize - 2) * step as isize;
+                        if qy < 0 || qy >= height as isize {
+                            continue;
+                        }
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as isize + (i as isize - 2) * step as isize;
+                            if qx < 0 || qx >= width as isize {
+                                continue;
+                            }
+                            let q = qy as usize * width + qx as usize;
+                            let distance = (i.abs_diff(2).max(j.abs_diff(2)) * step) as f64;
+
+                            let neighbour = texels[q];
+                            let color_weight = (-(neighbour.irradiance.luminance() - luminance)
+                                .abs()
+                                / color_scale)
+                                .exp();
+                            let weight = kx
+                                * ky
+                                * color_weight
+                                * self.normal_weight(guide.normals[p], guide.normals[q])
+                                * self.depth_weight(guide.depths[p], guide.depths[q], distance);
+
+                            sum += weight * neighbour.irradiance;
+                            variance += weight * weight * neighbour.variance.min(1e6);
+                            weights += weight;
+                        }
+                    }
+
+                    // The center always has a positive weight
+                    *out = Texel {
+                        irradiance: sum / weights,
+                        variance: variance / (weights * weights),
+                    };
+                }
+            });
+
+        filtered
+    }
+
+    fn normal_weight(&self, normal: Vec3, other: Vec3) -> f64 {
+        let (hit, other_hit) = (normal.len_squared() > 0.0, other.len_squared() > 0.0);
+        match (hit, other_hit) {
+            // Both see the background
+            (false, false) => 1.0,
+            (true, true) => dot(normal, other).max(0.0).powf(self.sigma_normal),
+            _ => 0.0,
+        }
+    }
+
+    fn depth_weight(&self, depth: f64, other: f64, distance: f64) -> f64 {
+        if depth <= 0.0 || other <= 0.0 {
+            // Background pixels were already told apart by their normals
+            return 1.0;
+        }
+        let relative = (depth - other).abs() / depth;
+        (-relative / (self.sigma_depth * distance.max(1.0))).exp()
+    }
+}
+
+/// Variance around pixel (x, y) averaged with a 3×3 Gaussian
+fn blurred_variance(texels: &[Texel], width: usize, height: usize, x: usize, y: usize) -> f64 {
+    const GAUSSIAN: [f64; 3] = [0.25, 0.5, 0.25];
+    let mut sum = 0.0;
+    let mut weights = 0.0;
+    for (j, ky) in GAUSSIAN.iter().enumerate() {
+        for (i, kx) in GAUSSIAN.iter().enumerate() {
+            let (Some(qx), Some(qy)) = ((x + i).checked_sub(1), (y + j).checked_sub(1)) else {
+                continue;
+            };
+            if qx >= width || qy >= height {
+                continue;
+            }
+            sum += kx * ky * texels[qy * width + qx].variance.min(1e6);
+            weights += kx * ky;
+        }
+    }
+    sum / weights
+}
+
+/// Features every iteration compares the pixels with
+struct Guide<'a> {
+    width: usize,
+    height: usize,
+    normals: &'a [Vec3],
+    depths: &'a [f64],
+}
+
+/// Albedo the radiance of a pixel is divided by, 1 for dark channels
+fn demodulation(albedo: Color) -> Color {
+    let channel = |a: f64| if a > DARK_ALBEDO { a } else { 1.0 };
+    Color::new(channel(albedo.x), channel(albedo.y), channel(albedo.z))
+}
@@ -0,0 +1,245 @@
+//! Light arriving from infinitely far away, picked up by rays leaving the
+//! scene: image based lighting from an equirectangular environment map, or
+//! the procedural [`Sky`](crate::sky::Sky).
+
+use std::{
+    f64::consts::PI,
+    fmt::{self, Debug},
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    image::Image,
+    random,
+    vector::{unit_vector, Vec3},
+};
+
+/// Surroundings of the scene, also sampled as a light
+pub trait Environment: Debug + Send + Sync {
+    /// Radiance coming from `direction`
+    fn radiance(&self, direction: Vec3) -> Color;
+
+    /// Density of sampling `direction` with [`Environment::random`], with
+    /// respect to solid angle
+    fn pdf_value(&self, direction: Vec3) -> f64;
+
+    /// Direction towards the environment, favouring the brighter parts
+    fn random(&self) -> Vec3;
+}
+
+pub type ArcEnvironment = Arc<dyn Environment>;
+
+/// Piecewise constant density over [0, 1)
+struct Distribution1D {
+    func: Vec<f64>,
+    /// Cumulative sums of `func`, normalized, starting with 0
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Density proportional to `func`, which must not be empty. Uniform when
+    /// every value is zero.
+    fn new(func: Vec<f64>) -> Self {
+        let n = func.len() as f64;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for value in func.iter() {
+            cdf.push(cdf.last().unwrap() + value / n);
+        }
+
+        let integral = *cdf.last().unwrap();
+        if integral > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        } else {
+            cdf.iter_mut()
+                .enumerate()
+                .for_each(|(i, c)| *c = i as f64 / n);
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Density at the `i`th interval
+    fn pdf(&self, i: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[i] / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    /// Point in [0, 1) distributed with the density, and its interval
+    fn sample(&self, u: f64) -> (f64, usize) {
+        let i = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.len() - 1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let offset = if width > 0.0 {
+            (u - self.cdf[i]) / width
+        } else {
+            0.0
+        };
+        ((i as f64 + offset) / self.len() as f64, i)
+    }
+}
+
+/// Equirectangular image, importance sampled with a distribution following
+/// its luminance
+pub struct EnvironmentMap {
+    image: Image,
+    intensity: f64,
+    /// Rotation around the vertical axis, in radians
+    rotation: f64,
+    /// Density of the rows, each with the density of its columns
+    rows: Distribution1D,
+    columns: Vec<Distribution1D>,
+}
+
+impl Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvironmentMap")
+            .field("width", &self.image.width)
+            .field("height", &self.image.height)
+            .field("intensity", &self.intensity)
+            .field("rotation", &self.rotation)
+            .finish()
+    }
+}
+
+impl EnvironmentMap {
+    /// Environment from an equirectangular image, its top row looking up
+    /// (+y) and its center towards +x, like the texture of a sphere
+    pub fn new(image: Image) -> Self {
+        let (width, height) = image.level_size(0);
+        let (width, height) = (width.max(1), height.max(1));
+
+        let luminance: Vec<f64> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| image.texel(0, x, y).luminance().max(0.0))
+            .collect();
+        // Radiance is interpolated with the neighbours, which must be
+        // covered by the density too
+        let neighbourhood = |x: usize, y: usize| {
+            let rows = y.saturating_sub(1)..=(y + 1).min(height - 1);
+            rows.flat_map(|y| [x + width - 1, x, x + 1].map(|x| luminance[y * width + x % width]))
+                .fold(0.0, f64::max)
+        };
+
+        // Texels shrink towards the poles, weight them by their solid angle
+        let columns: Vec<_> = (0..height)
+            .map(|y| {
+                let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+                let func = (0..width)
+                    .map(|x| neighbourhood(x, y) * sin_theta)
+                    .collect();
+                Distribution1D::new(func)
+            })
+            .collect();
+        let rows = Distribution1D::new(columns.iter().map(|c| c.integral).collect());
+
+        Self {
+            image,
+            intensity: 1.0,
+            rotation: 0.0,
+            rows,
+            columns,
+        }
+    }
+
+    /// Load an `.hdr`, `.exr` or any other image from the texture directory
+    pub fn open(filename: &str) -> Self {
+        Self::new(Image::new(filename))
+    }
+
+    /// Scale of the radiance of the image
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Rotate the environment around the vertical axis, in degrees
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    /// Texture coordinates of a direction, `v` going down from the top row
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = unit_vector(rotate_y(direction, -self.rotation));
+        let phi = (-d.z).atan2(d.x) + PI;
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        ((phi / (2.0 * PI)).fract(), theta / PI)
+    }
+
+    fn uv_to_direction(&self, (u, v): (f64, f64)) -> Vec3 {
+        let phi = 2.0 * PI * u - PI;
+        let theta = PI * v;
+        let d = Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            -theta.sin() * phi.sin(),
+        );
+        rotate_y(d, self.rotation)
+    }
+}
+
+impl Environment for EnvironmentMap {
+    /// Interpolated between texels
+    fn radiance(&self, direction: Vec3) -> Color {
+        let (width, height) = self.image.level_size(0);
+        if width == 0 || height == 0 {
+            return Color::BLACK;
+        }
+
+        let (u, v) = self.direction_to_uv(direction);
+        let x = u * width as f64 - 0.5;
+        let y = (v * height as f64 - 0.5).clamp(0.0, (height - 1) as f64);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        // Wraps around horizontally
+        let x0 = (x0 as isize).rem_euclid(width as isize) as usize;
+        let x1 = (x0 + 1) % width;
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(height - 1);
+
+        let texel = |x, y| self.image.texel(0, x, y);
+        let top = (1.0 - tx) * texel(x0, y0) + tx * texel(x1, y0);
+        let bottom = (1.0 - tx) * texel(x0, y1) + tx * texel(x1, y1);
+        self.intensity * ((1.0 - ty) * top + ty * bottom)
+    }
+
+    fn pdf_value(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let y = ((v * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        let columns = &self.columns[y];
+        let x = ((u * columns.len() as f64) as usize).min(columns.len() - 1);
+
+        // From the unit square to the sphere
+        self.rows.pdf(y) * columns.pdf(x) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self) -> Vec3 {
+        let (v, y) = self.rows.sample(random::float());
+        let (u, _) = self.columns[y].sample(random::float());
+        self.uv_to_direction((u, v))
+    }
+}
+
+fn rotate_y(v: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z)
+}
@@ -0,0 +1,250 @@
+use std::io;
+
+use crate::{
+    color::Color,
+    filter::Filter,
+    output::{self, SaveOptions},
+    vector::Vec3,
+};
+
+/// What a sample sees first, the denoiser uses it to tell edges and
+/// textures from noise
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Features {
+    /// Fraction of the light reflected by the first surface, or the
+    /// radiance of lights and of the background, clamped to 1
+    pub albedo: Color,
+    /// Normal of the first surface, zero if the sample missed the scene
+    pub normal: Vec3,
+    /// Distance to the first surface, zero if the sample missed the scene
+    pub depth: f64,
+}
+
+/// Accumulated samples of a single pixel
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pixel {
+    /// Sum of the linear radiance of the samples splatted onto the pixel,
+    /// weighted by the reconstruction filter
+    pub sum: Color,
+    /// Sum of the filter weights
+    pub weight: f64,
+    /// Number of samples taken inside of the pixel
+    pub samples: u32,
+    /// Sum of the features of the samples taken inside of the pixel
+    features: Features,
+    /// Running mean of the luminance of the samples and sum of the squared
+    /// differences to it, updated with Welford's algorithm
+    mean: f64,
+    m2: f64,
+}
+
+impl Pixel {
+    /// Luminance under which errors are measured relative to this value
+    /// instead, so nearly black pixels do not need exact averages
+    const DARK: f64 = 0.01;
+
+    /// Sum of the filter weights under which no sample really reached the
+    /// pixel. Negative lobes bring it arbitrarily close to zero, or below,
+    /// and dividing by it would blow the color up or flip its sign.
+    const MIN_WEIGHT: f64 = 1e-3;
+
+    /// Weighted average radiance of the pixel, black if no sample reached
+    /// it. Filters with negative lobes can make it negative, which is
+    /// clamped.
+    pub fn color(&self) -> Color {
+        if self.weight <= Self::MIN_WEIGHT {
+            return Color::BLACK;
+        }
+        let color = self.sum / self.weight;
+        Color::new(color.x.max(0.0), color.y.max(0.0), color.z.max(0.0))
+    }
+
+    /// Add a sample to the filtered color
+    pub fn splat(&mut self, color: Color, weight: f64) {
+        self.sum += weight * color;
+        self.weight += weight;
+    }
+
+    /// Average features of the samples taken inside of the pixel
+    pub fn features(&self) -> Features {
+        if self.samples == 0 {
+            return Features::default();
+        }
+        let samples = self.samples as f64;
+        Features {
+            albedo: self.features.albedo / samples,
+            normal: self.features.normal / samples,
+            depth: self.features.depth / samples,
+        }
+    }
+
+    /// Count a sample taken inside of the pixel in its error estimate and
+    /// features, the color is added with [`Pixel::splat`]
+    pub fn add(&mut self, color: Color, features: Features) {
+        self.samples += 1;
+        self.features.albedo += features.albedo;
+        self.features.normal += features.normal;
+        self.features.depth += features.depth;
+
+        let luminance = color.luminance();
+        let delta = luminance - self.mean;
+        self.mean += delta / self.samples as f64;
+        self.m2 += delta * (luminance - self.mean);
+    }
+
+    /// Combine with the samples of another pixel (Chan et al.)
+    pub fn merge(&mut self, other: &Pixel) {
+        self.sum += other.sum;
+        self.weight += other.weight;
+        self.features.albedo += other.features.albedo;
+        self.features.normal += other.features.normal;
+        self.features.depth += other.features.depth;
+
+        let samples = self.samples + other.samples;
+        if samples == 0 {
+            return;
+        }
+
+        let (a, b) = (self.samples as f64, other.samples as f64);
+        let delta = other.mean - self.mean;
+        self.mean += delta * b / samples as f64;
+        self.m2 += other.m2 + delta * delta * a * b / samples as f64;
+        self.samples = samples;
+    }
+
+    /// Sample variance of the luminance, infinite below two samples
+    pub fn variance(&self) -> f64 {
+        if self.samples < 2 {
+            return f64::INFINITY;
+        }
+        self.m2 / (self.samples - 1) as f64
+    }
+
+    /// Standard error of the average luminance, relative to it
+    pub fn relative_error(&self) -> f64 {
+        let standard_error = (self.variance() / self.samples as f64).sqrt();
+        standard_error / self.mean.max(Self::DARK)
+    }
+}
+
+/// In-memory framebuffer the camera renders into.
+/// Pixels are stored row by row starting from the top left corner.
+#[derive(Debug, Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Pixel>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Pixel::default(); width * height],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> &Pixel {
+        &self.pixels[y * self.width + x]
+    }
+
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    /// Add a sample taken at `offset`, in [0, 1)², from the top left corner
+    /// of pixel (x, y) and splat it onto the pixels around it
+    pub fn add_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        offset: (f64, f64),
+        color: Color,
+        features: Features,
+        filter: &Filter,
+    ) {
+        self.pixels[y * self.width + x].add(color, features);
+
+        // Pixels whose center is in (position - radius, position + radius]
+        let (px, py) = (x as f64 + offset.0, y as f64 + offset.1);
+        let range = |position: f64, size: usize| {
+            let first = (position - filter.radius - 0.5).floor() + 1.0;
+            let last = (position + filter.radius - 0.5).floor();
+            first.max(0.0) as usize..(last + 1.0).clamp(0.0, size as f64) as usize
+        };
+
+        for j in range(py, self.height) {
+            for i in range(px, self.width) {
+                let weight = filter.evaluate(i as f64 + 0.5 - px, j as f64 + 0.5 - py);
+                self.pixels[j * self.width + i].splat(color, weight);
+            }
+        }
+    }
+
+    /// Accumulate the samples of another film of the same size
+    pub fn merge(&mut self, other: &Film) {
+        assert_eq!((self.width, self.height), (other.width, other.height));
+        self.merge_at(other, 0, 0);
+    }
+
+    /// Accumulate the samples of a smaller film, e.g. a tile, whose top left
+    /// corner lands on pixel (x, y), possibly outside of this film. Pixels
+    /// falling outside are discarded.
+    pub fn merge_at(&mut self, other: &Film, x: isize, y: isize) {
+        // Part of `other` overlapping this film, in its own coordinates
+        let clip = |offset: isize, size: usize, other_size: usize| {
+            let start = (-offset).clamp(0, other_size as isize) as usize;
+            let end = (size as isize - offset).clamp(0, other_size as isize) as usize;
+            start..end.max(start)
+        };
+        let columns = clip(x, self.width, other.width);
+        let rows = clip(y, self.height, other.height);
+        let width = columns.len();
+
+        for row in rows {
+            let start =
+                (y + row as isize) as usize * self.width + (x + columns.start as isize) as usize;
+            let pixels = &mut self.pixels[start..start + width];
+            let other_start = row * other.width + columns.start;
+            let others = &other.pixels[other_start..other_start + width];
+
+            for (pixel, other) in pixels.iter_mut().zip(others) {
+                pixel.merge(other);
+            }
+        }
+    }
+
+    /// Average linear radiance of every pixel
+    pub fn colors(&self) -> Vec<Color> {
+        self.pixels.iter().map(Pixel::color).collect()
+    }
+
+    /// Save the film to `path`, the image format is deduced from the extension
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        output::save(path, self.width, self.height, &self.colors())
+    }
+
+    /// Same as [`Film::save`], with the settings of the format in `options`
+    pub fn save_with(&self, path: &str, options: &SaveOptions) -> io::Result<()> {
+        output::save_with(path, self.width, self.height, &self.colors(), options)
+    }
+
+    /// Save the number of samples of every pixel as a grey image, white for
+    /// the pixels with the most samples
+    pub fn save_sample_map(&self, path: &str) -> io::Result<()> {
+        let max = self
+            .pixels
+            .iter()
+            .map(|p| p.samples)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let counts: Vec<Color> = self
+            .pixels
+            .iter()
+            .map(|p| Color::WHITE * (p.samples as f64 / max as f64))
+            .collect();
+        output::save(path, self.width, self.height, &counts)
+    }
+}
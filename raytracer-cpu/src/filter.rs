@@ -0,0 +1,151 @@
+//! Pixel reconstruction filters.
+//!
+//! Every sample is splatted onto the pixels whose centers are within the
+//! filter radius of it, weighted by the filter at their offset, and each
+//! pixel is the weighted average of the samples it received. The box filter
+//! of radius 0.5 gives every sample to its own pixel only, wider filters
+//! trade some sharpness for less aliasing, the ones with negative lobes
+//! (Mitchell, Lanczos) sharpen the edges back.
+
+use std::{f64::consts::PI, str::FromStr};
+
+/// Filters that can be picked from the command line or a scene file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterKind {
+    #[default]
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+    BlackmanHarris,
+}
+
+impl FilterKind {
+    pub const NAMES: [&'static str; 6] = [
+        "box",
+        "tent",
+        "gaussian",
+        "mitchell",
+        "lanczos",
+        "blackman_harris",
+    ];
+
+    /// Radius, in pixels, the filter is usually given
+    pub fn default_radius(self) -> f64 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian => 1.5,
+            Self::Mitchell | Self::Lanczos | Self::BlackmanHarris => 2.0,
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(Self::Box),
+            "tent" => Ok(Self::Tent),
+            "gaussian" => Ok(Self::Gaussian),
+            "mitchell" => Ok(Self::Mitchell),
+            "lanczos" => Ok(Self::Lanczos),
+            "blackman_harris" => Ok(Self::BlackmanHarris),
+            _ => Err(format!(
+                "unknown filter '{s}', expected one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Separable filter, the weight of an offset is the product of the 1D
+/// kernel along both axes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    /// Distance in pixels past which samples get no weight
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::default())
+    }
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Weight of a sample at offset (`x`, `y`) from the center of a pixel
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.kernel(x) * self.kernel(y)
+    }
+
+    /// Pixels around the ones a sample lands in that it may contribute to
+    pub fn margin(&self) -> usize {
+        (self.radius - 0.5).max(0.0).ceil() as usize
+    }
+
+    fn kernel(&self, x: f64) -> f64 {
+        let r = self.radius;
+        let x = x.abs();
+        if x > r {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / r,
+            FilterKind::Gaussian => {
+                // Three standard deviations fit in the radius, shifted down so
+                // the weight reaches zero at the edge
+                let sigma = r / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(r)).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r, 1.0 / 3.0, 1.0 / 3.0),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r),
+            FilterKind::BlackmanHarris => {
+                let t = 2.0 * PI * (x + r) / (2.0 * r);
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+}
+
+/// Mitchell-Netravali cubic over [0, 2]
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let (x2, x3) = (x * x, x * x * x);
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+    } else if x < 2.0 {
+        (-b - 6.0 * c) * x3
+            + (6.0 * b + 30.0 * c) * x2
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    } else {
+        0.0
+    };
+    value / 6.0
+}
+
+/// Normalized sinc, sin(πx) / πx
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
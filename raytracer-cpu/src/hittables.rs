@@ -1,11 +1,13 @@
-use std::{f64::INFINITY, sync::Arc};
+use std::sync::Arc;
 
 use crate::{
     boundind_box::BoundingBox,
     interval::Interval,
     material::ArcMaterial,
+    matrix::Mat4,
+    random,
     ray::Ray,
-    vector::{dot, Vec3},
+    vector::{dot, unit_vector, Vec3},
 };
 
 pub type ArcHittable = Arc<dyn Hittable + Send + Sync>;
@@ -13,6 +15,59 @@ pub type ArcHittable = Arc<dyn Hittable + Send + Sync>;
 pub trait Hittable {
     fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord>;
     fn bounding_box(&self) -> BoundingBox;
+
+    /// Solid angle density of `random` generating `direction` from `origin`
+    /// at `time`.
+    /// Only objects that can be sampled as lights need to implement it.
+    #[allow(unused_variables)]
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
+        0.0
+    }
+
+    /// Random direction from `origin` towards a point on the object at `time`
+    #[allow(unused_variables)]
+    fn random(&self, origin: Vec3, time: f64) -> Vec3 {
+        Vec3::X
+    }
+
+    /// Closest hit of a surface, where shadow rays stop. Participating media
+    /// let them through and dim them by their `transmittance` instead.
+    fn hit_surface(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.hit(ray, interval)
+    }
+
+    /// Estimate of the fraction of light going through the media of the
+    /// object along `ray` within `interval`, 1 for surfaces
+    #[allow(unused_variables)]
+    fn transmittance(&self, ray: &Ray, interval: Interval) -> f64 {
+        1.0
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.as_ref().hit(ray, interval)
+    }
+
+    fn hit_surface(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.as_ref().hit_surface(ray, interval)
+    }
+
+    fn transmittance(&self, ray: &Ray, interval: Interval) -> f64 {
+        self.as_ref().transmittance(ray, interval)
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.as_ref().bounding_box()
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
+        self.as_ref().pdf_value(origin, direction, time)
+    }
+
+    fn random(&self, origin: Vec3, time: f64) -> Vec3 {
+        self.as_ref().random(origin, time)
+    }
 }
 
 pub struct HittableList {
@@ -20,6 +75,12 @@ pub struct HittableList {
     bbox: BoundingBox,
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> Self {
         HittableList {
@@ -56,135 +117,196 @@ impl Hittable for HittableList {
         record
     }
 
+    fn hit_surface(&self, ray: &Ray, mut interval: Interval) -> Option<HitRecord> {
+        let mut record = None;
+
+        for obj in self.objects.iter() {
+            if let Some(r) = obj.hit_surface(ray, interval) {
+                interval.max = r.distance;
+                record = Some(r);
+            }
+        }
+
+        record
+    }
+
+    fn transmittance(&self, ray: &Ray, interval: Interval) -> f64 {
+        self.objects
+            .iter()
+            .map(|obj| obj.transmittance(ray, interval))
+            .product()
+    }
+
     fn bounding_box(&self) -> BoundingBox {
         self.bbox.clone()
     }
-}
 
-// TODO: I'm not 100% sure why we need this, can't we simply place the object
-// somewhere else?
-// edit: Oooh, maybe we use this to reuse the same object multiple times?
-pub struct Translate {
-    offset: Vec3,
-    object: ArcHittable,
-    bbox: BoundingBox,
-}
+    // Objects are picked uniformly, so the density is the average
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
 
-impl Translate {
-    pub fn new(object: ArcHittable, offset: Vec3) -> Self {
-        let bbox = object.bounding_box() + offset;
-        Self {
-            offset,
-            object,
-            bbox,
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|obj| obj.pdf_value(origin, direction, time))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: Vec3, time: f64) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::X;
         }
+
+        let i = random::usize(0, self.objects.len());
+        self.objects[i].random(origin, time)
     }
 }
 
-impl Hittable for Translate {
-    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
-        let offset_ray = Ray {
-            origin: ray.origin - self.offset,
-            ..*ray
-        };
-
-        if let Some(mut record) = self.object.hit(&offset_ray, interval) {
-            record.point += self.offset;
-            return Some(record);
-        }
+/// Affine transformation along with its inverse, placing an object in the
+/// scene by bringing rays into object space
+#[derive(Debug, Clone, Copy)]
+pub struct Affine {
+    /// Object to world space
+    pub matrix: Mat4,
+    /// World to object space
+    pub inverse: Mat4,
+}
 
-        None
+impl Affine {
+    /// None if `matrix` is not invertible
+    pub fn new(matrix: Mat4) -> Option<Self> {
+        let inverse = matrix.inverse()?;
+        Some(Self { matrix, inverse })
     }
 
-    fn bounding_box(&self) -> BoundingBox {
-        self.bbox.clone()
+    /// Bounds of the transformed corners of `bbox`
+    pub fn bounding_box(&self, bbox: &BoundingBox) -> BoundingBox {
+        let mut transformed = BoundingBox::default();
+        for x in [bbox.x.min, bbox.x.max] {
+            for y in [bbox.y.min, bbox.y.max] {
+                for z in [bbox.z.min, bbox.z.max] {
+                    let corner = self.matrix.transform_point(Vec3::new(x, y, z));
+                    let corner = BoundingBox::from_extrema(corner, corner);
+                    transformed = BoundingBox::from_boxes(transformed, corner);
+                }
+            }
+        }
+        transformed
     }
-}
 
-fn lerp<T: Into<f64>>(alpha: T, first: f64, second: f64) -> f64 {
-    let a = alpha.into();
-    a * first + (1.0 - a) * second
-}
+    pub fn hit(&self, object: &impl Hittable, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let record = object.hit(&self.local_ray(ray), interval)?;
+        Some(self.world_record(record))
+    }
 
-pub struct RotateY {
-    cos_theta: f64,
-    sin_theta: f64,
-    object: ArcHittable,
-    bbox: BoundingBox,
-}
+    pub fn hit_surface(
+        &self,
+        object: &impl Hittable,
+        ray: &Ray,
+        interval: Interval,
+    ) -> Option<HitRecord> {
+        let record = object.hit_surface(&self.local_ray(ray), interval)?;
+        Some(self.world_record(record))
+    }
 
-impl RotateY {
-    pub fn new(object: ArcHittable, angle: f64) -> Self {
-        let radians = angle.to_radians();
-        let sin_theta = radians.sin();
-        let cos_theta = radians.cos();
-        let bbox = object.bounding_box();
+    pub fn transmittance(&self, object: &impl Hittable, ray: &Ray, interval: Interval) -> f64 {
+        object.transmittance(&self.local_ray(ray), interval)
+    }
+
+    /// `ray` in object space. The direction is not normalized so distances
+    /// match in both spaces.
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        Ray::new(
+            self.inverse.transform_point(ray.origin),
+            self.inverse.transform_vector(ray.direction),
+            ray.time,
+        )
+    }
 
-        let mut min = Vec3::new(INFINITY, INFINITY, INFINITY);
-        let mut max = Vec3::new(-INFINITY, -INFINITY, -INFINITY);
+    /// `record` of a hit in object space, brought to world space
+    fn world_record(&self, mut record: HitRecord) -> HitRecord {
+        record.point = self.matrix.transform_point(record.point);
+        record.normal = unit_vector(self.inverse.transform_normal(record.normal));
+        record.tangent = self.matrix.transform_vector(record.tangent);
+        record.bitangent = self.matrix.transform_vector(record.bitangent);
+        // Lengths scale by the cube root of the volume change on average
+        record.uv_density /= self.matrix.determinant3().abs().cbrt();
+        record
+    }
 
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let x = lerp(i, bbox.x.min, bbox.x.max);
-                    let y = lerp(j, bbox.y.min, bbox.y.max);
-                    let z = lerp(k, bbox.z.min, bbox.z.max);
+    pub fn pdf_value(
+        &self,
+        object: &impl Hittable,
+        origin: Vec3,
+        direction: Vec3,
+        time: f64,
+    ) -> f64 {
+        let local = self.inverse.transform_vector(unit_vector(direction));
+        let pdf = object.pdf_value(self.inverse.transform_point(origin), local, time);
+
+        // Jacobian of the change of solid angle, 1 for rigid transformations
+        pdf * self.inverse.determinant3().abs() / local.len().powi(3)
+    }
 
-                    let new_x = cos_theta * x + sin_theta * z;
-                    let new_z = -sin_theta * x + cos_theta * z;
+    pub fn random(&self, object: &impl Hittable, origin: Vec3, time: f64) -> Vec3 {
+        let local = object.random(self.inverse.transform_point(origin), time);
+        self.matrix.transform_vector(local)
+    }
+}
 
-                    let tester = Vec3::new(new_x, y, new_z);
+/// Instance of an object placed in the scene by an affine transformation.
+/// This also lets the same object be reused with different transformations.
+pub struct Transform {
+    object: ArcHittable,
+    affine: Affine,
+    bbox: BoundingBox,
+}
 
-                    for c in 0..3 {
-                        min[c] = min[c].min(tester[c]);
-                        max[c] = max[c].max(tester[c]);
-                    }
-                }
-            }
-        }
+impl Transform {
+    /// Panics if `matrix` is not invertible
+    pub fn new(object: ArcHittable, matrix: Mat4) -> Self {
+        let affine = Affine::new(matrix).expect("Transformation matrix should be invertible");
+        let bbox = affine.bounding_box(&object.bounding_box());
 
         Self {
-            cos_theta,
-            sin_theta,
             object,
+            affine,
             bbox,
         }
     }
 
-    fn rotate(&self, vec: Vec3) -> Vec3 {
-        Vec3::new(
-            self.cos_theta * vec.x - self.sin_theta * vec.z,
-            vec.y,
-            self.sin_theta * vec.x + self.cos_theta * vec.z,
-        )
-    }
-    fn rotate_neg(&self, vec: Vec3) -> Vec3 {
-        Vec3::new(
-            self.cos_theta * vec.x + self.sin_theta * vec.z,
-            vec.y,
-            -self.sin_theta * vec.x + self.cos_theta * vec.z,
-        )
+    pub fn matrix(&self) -> &Mat4 {
+        &self.affine.matrix
     }
 }
 
-impl Hittable for RotateY {
+impl Hittable for Transform {
     fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
-        let origin = self.rotate(ray.origin);
-        let direction = self.rotate(ray.direction);
-        let rotated = Ray::new(origin, direction, ray.time);
-
-        if let Some(mut record) = self.object.hit(&rotated, interval) {
-            record.point = self.rotate_neg(record.point);
-            record.normal = self.rotate_neg(record.normal);
-            return Some(record);
-        }
+        self.affine.hit(&self.object, ray, interval)
+    }
+
+    fn hit_surface(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.affine.hit_surface(&self.object, ray, interval)
+    }
 
-        None
+    fn transmittance(&self, ray: &Ray, interval: Interval) -> f64 {
+        self.affine.transmittance(&self.object, ray, interval)
     }
 
     fn bounding_box(&self) -> BoundingBox {
         self.bbox.clone()
     }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
+        self.affine.pdf_value(&self.object, origin, direction, time)
+    }
+
+    fn random(&self, origin: Vec3, time: f64) -> Vec3 {
+        self.affine.random(&self.object, origin, time)
+    }
 }
 
 /// Struct that keeps track of the hit point, the normal vector at that point,
@@ -197,6 +319,16 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub material: ArcMaterial,
     pub front_face: bool,
+    /// Derivatives of the point along the texture coordinates, ∂p/∂u and
+    /// ∂p/∂v, zero when the surface has no parametrization
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    /// Change of the texture coordinates per unit of length on the surface,
+    /// 0 when unknown
+    pub uv_density: f64,
+    /// Width of the area seen by the ray in texture coordinates, set by the
+    /// integrator to filter textures
+    pub footprint: f64,
 }
 
 impl HitRecord {
@@ -218,8 +350,23 @@ impl HitRecord {
             normal,
             material,
             front_face,
+            tangent: Vec3::default(),
+            bitangent: Vec3::default(),
+            uv_density: 0.0,
+            footprint: 0.0,
         }
     }
+
+    pub fn with_tangents(mut self, tangent: Vec3, bitangent: Vec3) -> Self {
+        self.tangent = tangent;
+        self.bitangent = bitangent;
+        self
+    }
+
+    pub fn with_uv_density(mut self, uv_density: f64) -> Self {
+        self.uv_density = uv_density;
+        self
+    }
 }
 
 impl PartialEq for HitRecord {
@@ -2,7 +2,7 @@ use std::{env, fs, io};
 
 use stb::image::Channels;
 
-use crate::color::Color;
+use crate::{color::Color, inflate};
 
 #[derive(Default)]
 pub struct Image {
@@ -12,40 +12,303 @@ pub struct Image {
     fdata: Vec<f32>,
     pub width: i32,
     pub height: i32,
+    // Downsampled copies of the image, each half the size of the previous one
+    mips: Vec<MipLevel>,
+}
+
+/// Level of the mip pyramid, RGB texels in scanline order
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<[f32; 3]>,
+}
+
+impl MipLevel {
+    fn texel(&self, x: usize, y: usize) -> [f32; 3] {
+        self.texels[y * self.width + x]
+    }
+
+    /// Half size level, every texel is the average of a 2x2 block. With odd
+    /// sizes the last row or column is folded into the previous block.
+    fn downsample(width: usize, height: usize, texel: impl Fn(usize, usize) -> [f32; 3]) -> Self {
+        let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+        let mut texels = Vec::with_capacity(half_width * half_height);
+
+        for y in 0..half_height {
+            for x in 0..half_width {
+                let (x0, x1) = (2 * x, (2 * x + 1).min(width - 1));
+                let (y0, y1) = (2 * y, (2 * y + 1).min(height - 1));
+                let block = [texel(x0, y0), texel(x1, y0), texel(x0, y1), texel(x1, y1)];
+                let mut sum = [0.0; 3];
+                for value in block {
+                    for c in 0..3 {
+                        sum[c] += 0.25 * value[c];
+                    }
+                }
+                texels.push(sum);
+            }
+        }
+
+        Self {
+            width: half_width,
+            height: half_height,
+            texels,
+        }
+    }
 }
 
 impl Image {
     pub fn new(filename: &str) -> Self {
-        let file_path = match env::var("IMAGE_DIR") {
+        Self::open(&Self::path(filename))
+    }
+
+    /// Like `new`, but failing instead of returning an empty image
+    pub fn try_new(filename: &str) -> Result<Self, String> {
+        Self::try_open(&Self::path(filename))
+    }
+
+    /// Path of an image of the texture directory
+    fn path(filename: &str) -> String {
+        match env::var("IMAGE_DIR") {
             Ok(dir) => format!("{dir}/{filename}"),
             Err(_) => format!("textures/{filename}"),
-        };
+        }
+    }
 
-        let file = match fs::File::open(&file_path) {
-            Ok(f) => f,
-            Err(_) => {
-                println!("Could not open '{file_path}'");
-                return Self::default();
-            }
-        };
+    /// Load the image at `file_path`, without looking into the texture directory
+    pub fn open(file_path: &str) -> Self {
+        Self::try_open(file_path).unwrap_or_else(|e| {
+            println!("{e}");
+            Self::default()
+        })
+    }
+
+    /// Like `open`, but failing instead of returning an empty image
+    pub fn try_open(file_path: &str) -> Result<Self, String> {
+        let file =
+            fs::File::open(file_path).map_err(|_| format!("Could not open '{file_path}'"))?;
 
         let mut reader = io::BufReader::new(file);
-        Self::load(&mut reader)
+        if file_path.to_lowercase().ends_with(".exr") {
+            return Self::load_exr(&mut reader)
+                .map_err(|e| format!("Could not load '{file_path}': {e}"));
+        }
+        let (info, data) = stb::image::stbi_loadf_from_reader(&mut reader, Channels::Rgb)
+            .ok_or_else(|| format!("Could not load '{file_path}'"))?;
+        Ok(Self::from_rgb(info.width, info.height, data.into_vec()))
     }
 
     pub fn load(reader: &mut io::BufReader<fs::File>) -> Self {
         let (info, data) = stb::image::stbi_loadf_from_reader(reader, Channels::Rgb)
             .expect("Should be able to load file with stb_image");
 
-        let bytes_per_pixel = info.components;
-        let bytes_per_scanline = info.width * bytes_per_pixel;
+        Self::from_rgb(info.width, info.height, data.into_vec())
+    }
 
-        Self {
-            fdata: data.into_vec(),
-            bytes_per_pixel: bytes_per_pixel as usize,
-            bytes_per_scanline: bytes_per_scanline as usize,
-            width: info.width,
-            height: info.height,
+    /// Image of linear RGB values in scanline order
+    fn from_rgb(width: i32, height: i32, fdata: Vec<f32>) -> Self {
+        let mut image = Self {
+            fdata,
+            bytes_per_pixel: 3,
+            bytes_per_scanline: 3 * width as usize,
+            width,
+            height,
+            mips: Vec::new(),
+        };
+        image.build_mips();
+        image
+    }
+
+    /// Load an OpenEXR image, stb does not read them. Only single part
+    /// scanline images are supported, uncompressed or with RLE, ZIPS or ZIP
+    /// compression, with half or float R, G and B channels (a single Y
+    /// channel is read as grey).
+    pub fn load_exr(reader: &mut impl io::Read) -> Result<Self, String> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        let mut exr = ExrReader {
+            bytes: &bytes,
+            pos: 0,
+        };
+
+        if exr.u32()? != 20000630 {
+            return Err("not an OpenEXR file".to_string());
+        }
+        // Tiled, deep and multi part images set flags in the version
+        if exr.u32()? & !0xff != 0 {
+            return Err("only scanline images are supported".to_string());
+        }
+
+        let mut channels = Vec::new();
+        let mut compression = None;
+        let mut window = None;
+        loop {
+            let name = exr.string()?;
+            if name.is_empty() {
+                break;
+            }
+            let _kind = exr.string()?;
+            let size = exr.u32()? as usize;
+            let value = exr.take(size)?;
+            let mut attribute = ExrReader {
+                bytes: value,
+                pos: 0,
+            };
+            match name.as_str() {
+                "channels" => loop {
+                    let channel = attribute.string()?;
+                    if channel.is_empty() {
+                        break;
+                    }
+                    let pixel_type = attribute.u32()?;
+                    // Linear flag, reserved bytes and sampling
+                    attribute.take(12)?;
+                    channels.push((channel, pixel_type));
+                },
+                "compression" => compression = value.first().copied(),
+                "dataWindow" => {
+                    let [x_min, y_min, x_max, y_max] = [(); 4].map(|_| attribute.i32());
+                    window = Some((x_min?, y_min?, x_max?, y_max?));
+                }
+                _ => {}
+            }
+        }
+
+        // Scanlines are stored in blocks of 16 with ZIP, one by one otherwise
+        let lines_per_block = match compression {
+            Some(0..=2) => 1,
+            Some(3) => 16,
+            _ => return Err("only RLE, ZIPS and ZIP compressions are supported".to_string()),
+        };
+        let (x_min, y_min, x_max, y_max) = window.ok_or("missing data window")?;
+        let (width, height) = (x_max - x_min + 1, y_max - y_min + 1);
+        if width <= 0 || height <= 0 {
+            return Err("empty data window".to_string());
+        }
+
+        // Channels are stored in alphabetical order, the ones we use are
+        // mapped to their RGB component
+        let target = |name: &str| match name {
+            "R" => Some(0),
+            "G" => Some(1),
+            "B" => Some(2),
+            "Y" => Some(3),
+            _ => None,
+        };
+        if !channels.iter().any(|(name, _)| target(name).is_some()) {
+            return Err("no R, G, B or Y channel".to_string());
+        }
+
+        let mut bytes_per_pixel = 0;
+        for (_, pixel_type) in channels.iter() {
+            bytes_per_pixel += match pixel_type {
+                1 => 2,
+                0 | 2 => 4,
+                _ => return Err("unknown pixel type".to_string()),
+            };
+        }
+
+        // One offset per block, the blocks themselves follow
+        let blocks = (height as usize).div_ceil(lines_per_block);
+        exr.take(8 * blocks)?;
+        let mut fdata = vec![0.0; 3 * (width * height) as usize];
+        for _ in 0..blocks {
+            let first = exr.i32()? - y_min;
+            let size = exr.u32()? as usize;
+            if first < 0 || first >= height || !(first as usize).is_multiple_of(lines_per_block) {
+                return Err("scanline outside of the data window".to_string());
+            }
+            let lines = lines_per_block.min((height - first) as usize);
+            let expected = lines * width as usize * bytes_per_pixel;
+
+            // Blocks that would not get smaller are stored as is
+            let data = exr.take(size)?;
+            let data = match compression {
+                _ if size == expected => data.to_vec(),
+                Some(1) => exr_predicted(rle_decompress(data, expected)?),
+                _ => exr_predicted(inflate::zlib_decompress(data, expected)?),
+            };
+            let mut block = ExrReader {
+                bytes: &data,
+                pos: 0,
+            };
+
+            // Every scanline holds all the values of a channel, then the next
+            for y in first as usize..first as usize + lines {
+                let row = &mut fdata[3 * y * width as usize..3 * (y + 1) * width as usize];
+                for (name, pixel_type) in channels.iter() {
+                    for x in 0..width as usize {
+                        let value = match pixel_type {
+                            0 => block.u32()? as f32,
+                            1 => half_to_f32(block.u16()?),
+                            _ => f32::from_bits(block.u32()?),
+                        };
+                        match target(name) {
+                            Some(3) => row[3 * x..3 * x + 3].fill(value),
+                            Some(c) => row[3 * x + c] = value,
+                            None => {}
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Self::from_rgb(width, height, fdata))
+    }
+
+    /// Values as stored in the file, for images holding data such as normal
+    /// maps. stb converts 8 bit images to linear colors with a 2.2 gamma when
+    /// loading them as floats, this converts them back.
+    pub fn raw(mut self) -> Self {
+        self.fdata
+            .iter_mut()
+            .for_each(|value| *value = value.powf(1.0 / 2.2));
+        self.mips.clear();
+        self.build_mips();
+        self
+    }
+
+    /// Build the mip pyramid down to a single texel
+    fn build_mips(&mut self) {
+        if self.fdata.is_empty() || self.width <= 0 || self.height <= 0 {
+            return;
+        }
+
+        let (mut width, mut height) = (self.width as usize, self.height as usize);
+        while width > 1 || height > 1 {
+            let level = match self.mips.last() {
+                Some(last) => MipLevel::downsample(width, height, |x, y| last.texel(x, y)),
+                None => MipLevel::downsample(width, height, |x, y| {
+                    let idx = y * self.bytes_per_scanline + x * self.bytes_per_pixel;
+                    [self.fdata[idx], self.fdata[idx + 1], self.fdata[idx + 2]]
+                }),
+            };
+            (width, height) = (level.width, level.height);
+            self.mips.push(level);
+        }
+    }
+
+    /// Number of levels in the mip pyramid, the full size image included
+    pub fn levels(&self) -> usize {
+        1 + self.mips.len()
+    }
+
+    /// Width and height of a level of the mip pyramid
+    pub fn level_size(&self, level: usize) -> (usize, usize) {
+        match level {
+            0 => (self.width.max(0) as usize, self.height.max(0) as usize),
+            _ => {
+                let mip = &self.mips[level - 1];
+                (mip.width, mip.height)
+            }
+        }
+    }
+
+    /// Color of texel (x,y) of a level of the mip pyramid
+    pub fn texel(&self, level: usize, x: usize, y: usize) -> Color {
+        match level {
+            0 => self.pixel_color(x, y),
+            _ => Color::from_f32_slice(&self.mips[level - 1].texel(x, y)),
         }
     }
 
@@ -76,3 +339,104 @@ impl Image {
         high - 1
     }
 }
+
+/// Little endian reader over the bytes of an OpenEXR file
+struct ExrReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ExrReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or("unexpected end of file")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Null terminated string
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.bytes[self.pos.min(self.bytes.len())..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or("unterminated string")?;
+        let bytes = self.take(len + 1)?;
+        Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+}
+
+/// Undo the run length encoding of RLE compressed OpenEXR blocks: a negative
+/// count is followed by as many literal bytes, a positive count `n` by a
+/// byte repeated `n + 1` times
+fn rle_decompress(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(size);
+    let mut bytes = data.iter();
+    while let Some(&count) = bytes.next() {
+        let count = count as i8;
+        if count < 0 {
+            let literals = bytes.as_slice().get(..count.unsigned_abs() as usize);
+            out.extend_from_slice(literals.ok_or("truncated RLE block")?);
+            bytes.nth(count.unsigned_abs() as usize - 1);
+        } else {
+            let &value = bytes.next().ok_or("truncated RLE block")?;
+            out.extend(std::iter::repeat_n(value, count as usize + 1));
+        }
+    }
+    if out.len() != size {
+        return Err(format!("expected {size} bytes, decompressed {}", out.len()));
+    }
+    Ok(out)
+}
+
+/// Undo the preprocessing of RLE and ZIP compressed OpenEXR blocks: bytes
+/// are stored as differences to the previous one, and the even bytes of the
+/// data before the odd ones
+fn exr_predicted(mut data: Vec<u8>) -> Vec<u8> {
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+
+    let half = data.len().div_ceil(2);
+    let (even, odd) = data.split_at(half);
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in even.iter().enumerate() {
+        out.push(byte);
+        if let Some(&byte) = odd.get(i) {
+            out.push(byte);
+        }
+    }
+    out
+}
+
+/// Convert an IEEE 754 half precision float
+fn half_to_f32(half: u16) -> f32 {
+    let sign = ((half >> 15) as u32) << 31;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        // Subnormal, the value is mantissa * 2^-24
+        0 => {
+            let value = mantissa as f32 * 2f32.powi(-24);
+            return if sign != 0 { -value } else { value };
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
@@ -1,4 +1,4 @@
-use std::{f64::INFINITY, ops};
+use std::ops;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Interval {
@@ -19,32 +19,35 @@ impl Interval {
 
     pub fn empty() -> Self {
         Interval {
-            min: INFINITY,
-            max: -INFINITY,
+            min: f64::INFINITY,
+            max: -f64::INFINITY,
         }
     }
 
     pub fn _universe() -> Self {
         Interval {
-            min: -INFINITY,
-            max: INFINITY,
+            min: -f64::INFINITY,
+            max: f64::INFINITY,
         }
     }
 
     pub fn with_min(min: f64) -> Self {
-        Interval { min, max: INFINITY }
+        Interval {
+            min,
+            max: f64::INFINITY,
+        }
     }
 
     pub fn positive() -> Self {
         Interval {
             min: 0.001,
-            max: INFINITY,
+            max: f64::INFINITY,
         }
     }
 
     pub fn _negative() -> Self {
         Interval {
-            min: -INFINITY,
+            min: -f64::INFINITY,
             max: 0.0,
         }
     }
@@ -0,0 +1,40 @@
+//! CPU path tracer following the "Ray Tracing In One Weekend" book series.
+//!
+//! The binary in `main.rs` is a thin client of this library: scenes are
+//! built with the functions in [`scenes`] and rendered through [`camera::Camera`].
+
+pub mod animation;
+pub mod boundind_box;
+pub mod bvh;
+pub mod camera;
+pub mod color;
+pub mod denoise;
+pub mod environment;
+pub mod film;
+pub mod filter;
+pub mod hittables;
+pub mod image;
+pub mod inflate;
+pub mod interval;
+pub mod material;
+pub mod matrix;
+pub mod mesh;
+pub mod microfacet;
+pub mod normal_map;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod pdf;
+pub mod perlin;
+pub mod progress;
+pub mod quad;
+pub mod random;
+pub mod ray;
+pub mod sampler;
+pub mod scenes;
+pub mod sky;
+pub mod sphere;
+pub mod texture;
+pub mod utils;
+pub mod vector;
+pub mod volumes;
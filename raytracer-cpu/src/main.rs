@@ -1,48 +1,436 @@
-use std::{collections::HashMap, env, process};
-
-mod boundind_box;
-mod camera;
-mod color;
-mod hittables;
-mod image;
-mod interval;
-mod material;
-mod perlin;
-mod quad;
-mod random;
-mod ray;
-mod scenes;
-mod sphere;
-mod texture;
-mod utils;
-mod vector;
-mod volumes;
+use std::{
+    env,
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+    process,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-fn main() {
-    let scene_names = HashMap::from([
-        ("bouncing_spheres", scenes::bouncing_spheres as fn()),
-        ("checkered_spheres", scenes::checkered_spheres as fn()),
-        ("earth", scenes::earth as fn()),
-        ("quads", scenes::quads as fn()),
-        ("light", scenes::light as fn()),
-        ("perlin", scenes::perlin_spheres as fn()),
-        ("cornell_box", scenes::cornell_box as fn()),
-        ("cornell_smoke", scenes::cornell_smoke as fn()),
-        ("the_week_after", scenes::the_week_after as fn()),
-    ]);
-
-    let usage = || {
-        println!("USAGE: raytracer-cpu <scene_name>\n\nValid scene names:");
-        scene_names.keys().for_each(|s| println!("    - {s}"));
+use raytracer_cpu::{
+    bvh,
+    camera::{Camera, CameraConfig},
+    denoise::Denoiser,
+    filter::{Filter, FilterKind},
+    output::{self, SaveOptions},
+    progress::{Progress, ProgressReporter},
+    sampler::SamplerKind,
+    scenes::{self, Scene, SceneBuilder},
+};
+
+const OPTIONS: &str = "\
+OPTIONS:
+    --width <pixels>     Image width, the height follows the aspect ratio
+    --samples <n>        Number of samples per pixel
+    --max-depth <n>      Maximum number of ray bounces
+    --output <file>      Output image, format chosen from the extension
+                         (.ppm, .png, .hdr, .pfm) [default: out.ppm]
+    --bit-depth <n>      Bits per channel of PNG images, 8 or 16 [default: 8]
+    --threads <n>        Number of render threads [default: all cores]
+    --seed <n>           Seed for the random number generator [default: 0]
+    --sampler <name>     Pattern of the samples in each pixel: independent,
+                         stratified, halton, sobol or blue_noise
+                         [default: independent]
+    --filter <name>      Reconstruction filter: box, tent, gaussian,
+                         mitchell, lanczos or blackman_harris [default: box]
+    --filter-radius <r>  Filter radius in pixels [default: depends on the
+                         filter]
+    --adaptive <error>   Stop sampling a pixel once its relative error is
+                         below this, `--samples` becomes the maximum
+    --min-samples <n>    Samples in every pixel before adding more where
+                         needed [default: 16]
+    --time-limit <secs>  Stop adding samples after this long
+    --sample-map <file>  Also save the number of samples of every pixel, one
+                         file per frame like the output
+    --frames <a..b>      Render frames a to b (excluded, or `a..=b` to include
+                         it) of an animation, numbered after the output name
+    --denoise            Remove the noise of the image before saving it
+    --bvh-stats          Print statistics of the acceleration structures
+    -h, --help           Print this message";
+
+const FLAGS: [&str; 15] = [
+    "--width",
+    "--samples",
+    "--max-depth",
+    "--output",
+    "--bit-depth",
+    "--threads",
+    "--seed",
+    "--sampler",
+    "--filter",
+    "--filter-radius",
+    "--adaptive",
+    "--min-samples",
+    "--time-limit",
+    "--sample-map",
+    "--frames",
+];
+
+fn usage() {
+    println!("USAGE: raytracer-cpu [OPTIONS] <scene_name>");
+    println!("       raytracer-cpu [OPTIONS] render <scene_file.toml>\n");
+    println!("{OPTIONS}\n\nValid scene names:");
+    scenes::SCENES
+        .iter()
+        .for_each(|(s, _)| println!("    - {s}"));
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {message}\n");
+    usage();
+    process::exit(1)
+}
+
+/// Settings given on the command line, applied on top of the scene
+#[derive(Default)]
+struct Options {
+    width: Option<i32>,
+    samples: Option<i32>,
+    max_depth: Option<i32>,
+    output: Option<String>,
+    save: SaveOptions,
+    threads: Option<usize>,
+    seed: Option<u64>,
+    sampler: Option<SamplerKind>,
+    filter: Option<FilterKind>,
+    filter_radius: Option<f64>,
+    adaptive: Option<f64>,
+    min_samples: Option<i32>,
+    time_limit: Option<f64>,
+    sample_map: Option<String>,
+    frames: Option<Range<u32>>,
+    denoise: bool,
+    bvh_stats: bool,
+    /// Remaining positional arguments
+    args: Vec<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Self {
+        let mut options = Self::default();
+
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                usage();
+                process::exit(0)
+            }
+
+            if !arg.starts_with("--") {
+                options.args.push(arg);
+                continue;
+            }
+
+            if arg == "--denoise" {
+                options.denoise = true;
+                continue;
+            }
+
+            if arg == "--bvh-stats" {
+                options.bvh_stats = true;
+                continue;
+            }
+
+            // Accept both `--flag value` and `--flag=value`
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), value.to_string()),
+                None if !FLAGS.contains(&arg.as_str()) => fail(&format!("unknown option '{arg}'")),
+                None => {
+                    let value = args
+                        .next()
+                        .unwrap_or_else(|| fail(&format!("missing value for '{arg}'")));
+                    (arg, value)
+                }
+            };
+
+            match flag.as_str() {
+                "--width" => options.width = Some(parse_value(&flag, &value)),
+                "--samples" => options.samples = Some(parse_value(&flag, &value)),
+                "--max-depth" => options.max_depth = Some(parse_value(&flag, &value)),
+                "--output" => options.output = Some(value),
+                "--bit-depth" => match value.as_str() {
+                    "8" => options.save.sixteen_bit = false,
+                    "16" => options.save.sixteen_bit = true,
+                    _ => fail(&format!(
+                        "invalid value '{value}' for '{flag}', expected 8 or 16"
+                    )),
+                },
+                "--threads" => options.threads = Some(parse_value(&flag, &value)),
+                "--seed" => options.seed = Some(parse_value(&flag, &value)),
+                "--sampler" => {
+                    options.sampler = Some(value.parse().unwrap_or_else(|e: String| fail(&e)))
+                }
+                "--filter" => {
+                    options.filter = Some(value.parse().unwrap_or_else(|e: String| fail(&e)))
+                }
+                "--filter-radius" => {
+                    let radius: f64 = parse_value(&flag, &value);
+                    if !(radius > 0.0 && radius.is_finite()) {
+                        fail(&format!("invalid value '{value}' for '{flag}'"))
+                    }
+                    options.filter_radius = Some(radius)
+                }
+                "--adaptive" => options.adaptive = Some(parse_value(&flag, &value)),
+                "--min-samples" => options.min_samples = Some(parse_value(&flag, &value)),
+                "--time-limit" => {
+                    let seconds: f64 = parse_value(&flag, &value);
+                    if !(seconds > 0.0 && seconds.is_finite()) {
+                        fail(&format!("invalid value '{value}' for '{flag}'"))
+                    }
+                    options.time_limit = Some(seconds)
+                }
+                "--sample-map" => options.sample_map = Some(value),
+                "--frames" => options.frames = Some(parse_frames(&value)),
+                _ => fail(&format!("unknown option '{flag}'")),
+            }
+        }
+
+        options
+    }
+
+    fn apply(&self, config: &mut CameraConfig) {
+        if let Some(width) = self.width {
+            config.image_width = width;
+        }
+        if let Some(samples) = self.samples {
+            config.samples = samples;
+        }
+        if let Some(max_depth) = self.max_depth {
+            config.max_depth = max_depth;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = seed;
+        }
+        if let Some(sampler) = self.sampler {
+            config.sampler = sampler;
+        }
+        if let Some(kind) = self.filter {
+            config.filter = Filter::new(kind);
+        }
+        if let Some(radius) = self.filter_radius {
+            config.filter = config.filter.with_radius(radius);
+        }
+        if let Some(threshold) = self.adaptive {
+            config.adaptive_threshold = Some(threshold);
+        }
+        if let Some(min_samples) = self.min_samples {
+            config.min_samples = min_samples;
+        }
+        if let Some(time_limit) = self.time_limit {
+            config.time_limit = Some(Duration::from_secs_f64(time_limit));
+        }
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("invalid value '{value}' for '{flag}'")))
+}
+
+/// Frame range given as `a..b`, `a..=b` or a single frame
+fn parse_frames(value: &str) -> Range<u32> {
+    // Ranges are exclusive, the frame after the last must fit too
+    let after = |last: u32| {
+        last.checked_add(1)
+            .unwrap_or_else(|| fail(&format!("last frame of '{value}' is too large")))
     };
+    let range = if let Some((start, end)) = value.split_once("..=") {
+        parse_value("--frames", start)..after(parse_value("--frames", end))
+    } else if let Some((start, end)) = value.split_once("..") {
+        parse_value("--frames", start)..parse_value("--frames", end)
+    } else {
+        let frame = parse_value("--frames", value);
+        frame..after(frame)
+    };
+
+    if range.is_empty() {
+        fail(&format!("empty frame range '{value}'"))
+    }
+    range
+}
 
-    let scene = match env::args().nth(1) {
-        Some(v) => v,
-        None => {
+/// Path of a frame of an animation, e.g. `out_0012.png` for `out.png`
+fn frame_path(output: &str, frame: u32) -> String {
+    let path = Path::new(output);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}_{frame:04}.{}", ext.to_string_lossy()),
+        None => format!("{stem}_{frame:04}"),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Where the scene comes from, rebuilt for every frame of an animation
+enum SceneSource {
+    File(String),
+    Builtin(SceneBuilder),
+}
+
+impl SceneSource {
+    fn load(&self, time: f64, seed: u64) -> Scene {
+        let mut scene = match self {
+            Self::File(path) => match scenes::file::load_at(path, time) {
+                Ok(scene) => scene,
+                Err(e) => {
+                    eprintln!("Failed to load scene: {e}");
+                    process::exit(1)
+                }
+            },
+            Self::Builtin(build) => build(seed),
+        };
+        scene.camera.time = time;
+        scene
+    }
+}
+
+/// Progress bar drawn on stderr, redrawn at most every 100ms
+struct ProgressBar {
+    last_draw: Mutex<Option<Instant>>,
+}
+
+impl ProgressBar {
+    const WIDTH: usize = 40;
+
+    fn new() -> Self {
+        Self {
+            last_draw: Mutex::new(None),
+        }
+    }
+
+    fn draw(progress: &Progress, eta: &str) {
+        let filled = (progress.fraction() * Self::WIDTH as f64) as usize;
+        let bar = format!(
+            "{}{}",
+            "#".repeat(filled),
+            "-".repeat(Self::WIDTH - filled.min(Self::WIDTH))
+        );
+
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r[{bar}] {:>5.1}%  {:>7.2}M samples/s  {eta}   ",
+            100.0 * progress.fraction(),
+            progress.samples_per_sec() / 1e6,
+        );
+        let _ = stderr.flush();
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+impl ProgressReporter for ProgressBar {
+    fn update(&self, progress: &Progress) {
+        let Ok(mut last_draw) = self.last_draw.try_lock() else {
+            return;
+        };
+        if last_draw.is_some_and(|t| t.elapsed() < Duration::from_millis(100)) {
+            return;
+        }
+        *last_draw = Some(Instant::now());
+
+        let eta = progress.eta().map_or("ETA --:--:--".to_string(), |eta| {
+            format!("ETA {}", format_duration(eta))
+        });
+        Self::draw(progress, &eta);
+    }
+
+    fn finish(&self, progress: &Progress) {
+        let elapsed = format!("done in {}", format_duration(progress.elapsed));
+        Self::draw(progress, &elapsed);
+        eprintln!();
+    }
+}
+
+fn main() {
+    let options = Options::parse(env::args().skip(1));
+
+    if let Some(threads) = options.threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            eprintln!("Could not set the number of threads: {e}")
+        }
+    }
+
+    bvh::print_stats(options.bvh_stats);
+
+    let source = match options.args.as_slice() {
+        [command, path] if command == "render" => SceneSource::File(path.clone()),
+        [name] => match scenes::SCENES
+            .iter()
+            .find(|(scene_name, _)| scene_name == name)
+        {
+            Some((_, build)) => SceneSource::Builtin(*build),
+            None => fail(&format!("unknown scene '{name}'")),
+        },
+        [] => {
             usage();
             process::exit(0)
         }
+        _ => fail("too many arguments"),
+    };
+
+    let output = options.output.as_deref().unwrap_or("out.ppm");
+    if output::writer_for_path(output).is_none() {
+        fail(&format!("unsupported output format '{output}'"))
+    }
+
+    let seed = options.seed.unwrap_or_default();
+    let Some(frames) = options.frames.clone() else {
+        render(source.load(0.0, seed), &options, output, None);
+        return;
     };
 
-    scene_names.get(scene.as_str()).map_or_else(usage, |f| f())
+    // The frame rate is part of the scene, load it once to read it
+    let fps = source.load(0.0, seed).camera.fps;
+    if fps <= 0.0 {
+        fail("the frame rate of the scene must be positive")
+    }
+
+    for frame in frames.clone() {
+        eprintln!(
+            "Frame {frame} ({} of {})",
+            frame - frames.start + 1,
+            frames.len()
+        );
+        let scene = source.load(frame as f64 / fps, seed);
+        render(scene, &options, output, Some(frame));
+    }
+}
+
+/// Render `scene` to `output`, numbered after `frame` in animations like the
+/// sample map
+fn render(mut scene: Scene, options: &Options, output: &str, frame: Option<u32>) {
+    options.apply(&mut scene.camera);
+    if scene.camera.image_width <= 0 || scene.camera.samples <= 0 {
+        fail("image width and samples must be positive")
+    }
+
+    let camera = Camera::new(scene.camera);
+    let film = camera.render_with_progress(scene.world, &scene.lights, &ProgressBar::new());
+    let path = |file: &str| match frame {
+        Some(frame) => frame_path(file, frame),
+        None => file.to_string(),
+    };
+    let output = &path(output);
+    let saved = if options.denoise {
+        let colors = Denoiser::default().denoise(&film);
+        output::save_with(output, film.width, film.height, &colors, &options.save)
+    } else {
+        film.save_with(output, &options.save)
+    };
+    if let Err(e) = saved {
+        eprintln!("Failed to save the image with error: {e}")
+    }
+    if let Some(sample_map) = &options.sample_map {
+        if let Err(e) = film.save_sample_map(&path(sample_map)) {
+            eprintln!("Failed to save the sample map with error: {e}")
+        }
+    }
 }
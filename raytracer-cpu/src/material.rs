@@ -1,89 +1,151 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
     color::Color,
     hittables::HitRecord,
+    microfacet::{self, ConductorPdf, DielectricPdf, Ggx},
+    normal_map::NormalMap,
+    onb::Onb,
+    pdf::{self, CosinePdf, HenyeyGreensteinPdf, Pdf, SpherePdf, WeightedPdf},
     random,
     ray::Ray,
     texture::{ArcTexture, SolidColor},
     vector::{dot, unit_vector, Vec3},
 };
 
+/// How a material scatters an incoming ray
+pub enum Scatter {
+    /// A single outgoing ray and the fraction of light it carries, e.g.
+    /// mirror reflection or refraction
+    Specular { ray: Ray, attenuation: Color },
+    /// Outgoing directions are sampled from a density and weighted with
+    /// [`Material::eval`], which lets the integrator combine it with light
+    /// sampling
+    Pdf(Box<dyn Pdf>),
+}
+
 pub trait Material {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Ray, Color)>;
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<Scatter>;
+
+    /// Fraction of the light coming along `scattered` that is scattered back
+    /// along `ray`, per unit solid angle: the BSDF times the cosine with the
+    /// normal. Only used for materials scattering with `Scatter::Pdf`.
+    #[allow(unused_variables)]
+    fn eval(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        Color::BLACK
+    }
+
+    /// Density of `scatter` sampling the direction of `scattered`, with
+    /// respect to solid angle. 0 for specular scattering.
+    fn pdf(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> f64 {
+        match self.scatter(ray, record) {
+            Some(Scatter::Pdf(pdf)) => pdf.value(scattered.direction),
+            _ => 0.0,
+        }
+    }
 
     #[allow(unused_variables)]
     fn emit(&self, uv: (f64, f64), point: Vec3) -> Color {
         Color::BLACK
     }
+
+    /// Whether `emit` may be anything but black, emissive surfaces are
+    /// sampled as lights
+    fn is_emissive(&self) -> bool {
+        false
+    }
 }
 
 pub type ArcMaterial = Arc<dyn Material + Send + Sync>;
 
+/// Normal to shade `record` with, perturbed by the normal map if any
+fn shading_normal(normal_map: &Option<NormalMap>, record: &HitRecord) -> Vec3 {
+    normal_map
+        .as_ref()
+        .map_or(record.normal, |normal_map| normal_map.normal(record))
+}
+
 pub struct Lambertian {
     texture: ArcTexture,
+    normal_map: Option<NormalMap>,
 }
 
 impl Default for Lambertian {
     fn default() -> Self {
-        Self {
-            texture: Arc::new(SolidColor::default()),
-        }
+        Self::new(Arc::new(SolidColor::default()))
     }
 }
 
 impl Lambertian {
     pub fn from_albedo(albedo: Color) -> Self {
-        Self {
-            texture: Arc::new(SolidColor::new(albedo)),
-        }
+        Self::new(Arc::new(SolidColor::new(albedo)))
     }
 
     pub fn from_rgb(r: f64, g: f64, b: f64) -> Self {
+        Self::new(Arc::new(SolidColor::from_rgb(r, g, b)))
+    }
+
+    pub fn new(texture: ArcTexture) -> Self {
         Self {
-            texture: Arc::new(SolidColor::from_rgb(r, g, b)),
+            texture,
+            normal_map: None,
         }
     }
 
-    pub fn new(texture: ArcTexture) -> Self {
-        Self { texture }
+    pub fn with_normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Ray, Color)> {
-        let mut scatter_direction = record.normal + Vec3::random_in_unit_sphere();
+    fn scatter(&self, _: &Ray, record: &HitRecord) -> Option<Scatter> {
+        let normal = shading_normal(&self.normal_map, record);
+        Some(Scatter::Pdf(Box::new(CosinePdf::new(normal))))
+    }
 
-        if scatter_direction.near_zero() {
-            scatter_direction = record.normal;
-        }
+    fn eval(&self, _: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        let normal = shading_normal(&self.normal_map, record);
+        let cosine = dot(normal, unit_vector(scattered.direction));
+        let albedo = self
+            .texture
+            .filtered(record.uv, record.point, record.footprint);
+        albedo * (cosine.max(0.0) / PI)
+    }
 
-        Some((
-            // scattered ray
-            Ray::new(record.point, scatter_direction, ray.time),
-            // attenuation
-            self.texture.value(record.uv, record.point),
-        ))
+    fn pdf(&self, _: &Ray, record: &HitRecord, scattered: &Ray) -> f64 {
+        let normal = shading_normal(&self.normal_map, record);
+        let cosine = dot(normal, unit_vector(scattered.direction));
+        cosine.max(0.0) / PI
     }
 }
 
+/// Mirror with a random perturbation of the reflected direction, see
+/// [`Conductor`] for physically based rough metals
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    normal_map: Option<NormalMap>,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
-        Self { albedo, fuzz }
-    }
-
-    pub fn from_rgb(rgb: (f64, f64, f64), fuzz: f64) -> Self {
         Self {
-            albedo: Color::new(rgb.0, rgb.1, rgb.2),
+            albedo,
             fuzz,
+            normal_map: None,
         }
     }
 
+    pub fn from_rgb(rgb: (f64, f64, f64), fuzz: f64) -> Self {
+        Self::new(Color::new(rgb.0, rgb.1, rgb.2), fuzz)
+    }
+
+    pub fn with_normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
     fn reflectance(&self, cos: f64) -> Color {
         // Schlink's approximation for metals
         self.albedo + (Color::WHITE - self.albedo) * (1.0 - cos).powi(5)
@@ -91,44 +153,538 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Ray, Color)> {
-        let reflected = unit_vector(reflect(ray.direction, record.normal))
-            + self.fuzz * Vec3::random_in_unit_sphere();
-
-        let ray_direction = reflected + self.fuzz * Vec3::random_in_unit_sphere();
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+        let normal = shading_normal(&self.normal_map, record);
+        let ray_direction =
+            unit_vector(reflect(ray.direction, normal)) + self.fuzz * Vec3::random_in_unit_sphere();
         let scattered = Ray::new(record.point, ray_direction, ray.time);
 
-        let cosine = dot(scattered.direction, record.normal);
-        if cosine > 0.0 {
-            return Some((scattered, self.reflectance(cosine)));
+        // Reflections must also leave the actual surface
+        let cosine = dot(unit_vector(scattered.direction), normal);
+        if cosine > 0.0 && dot(scattered.direction, record.normal) > 0.0 {
+            return Some(Scatter::Specular {
+                ray: scattered,
+                attenuation: self.reflectance(cosine),
+            });
         }
         None
     }
 }
 
+/// Metal with a GGX microfacet distribution, smooth when the roughness is 0
+pub struct Conductor {
+    albedo: Color,
+    ggx: Ggx,
+    normal_map: Option<NormalMap>,
+}
+
+impl Conductor {
+    /// `albedo` is the reflectance at normal incidence
+    pub fn new(albedo: Color, roughness: f64) -> Self {
+        Self {
+            albedo,
+            ggx: Ggx::from_roughness(roughness),
+            normal_map: None,
+        }
+    }
+
+    pub fn with_normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    fn reflectance(&self, cos: f64) -> Color {
+        // Schlick's approximation, which stays below 1 for albedos below 1
+        let cos = cos.clamp(0.0, 1.0);
+        self.albedo + (Color::WHITE - self.albedo) * (1.0 - cos).powi(5)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::new(shading_normal(&self.normal_map, record));
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        if self.ggx.is_smooth() {
+            let direction = frame.to_world(microfacet::reflect(wo, Vec3::new(0.0, 0.0, 1.0)));
+            return Some(Scatter::Specular {
+                ray: Ray::new(record.point, direction, ray.time),
+                attenuation: self.reflectance(wo.z),
+            });
+        }
+
+        Some(Scatter::Pdf(Box::new(ConductorPdf {
+            frame,
+            wo,
+            ggx: self.ggx,
+        })))
+    }
+
+    fn eval(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        let frame = Onb::new(shading_normal(&self.normal_map, record));
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        let wi = frame.to_local(unit_vector(scattered.direction));
+        if wo.z <= 0.0 || wi.z <= 0.0 || dot(scattered.direction, record.normal) <= 0.0 {
+            return Color::BLACK;
+        }
+
+        // D G F / (4 cos_o cos_i), times cos_i
+        let h = unit_vector(wo + wi);
+        let specular = self.ggx.d(h) * self.ggx.g(wo, wi) / (4.0 * wo.z);
+        self.reflectance(dot(wi, h)) * specular
+    }
+}
+
 pub struct Dielectric {
     eta: f64, // refraction_index
+    ggx: Ggx,
+    normal_map: Option<NormalMap>,
 }
 
 impl Dielectric {
     pub fn new(eta: f64) -> Self {
-        Self { eta }
+        Self {
+            eta,
+            ggx: Ggx::from_roughness(0.0),
+            normal_map: None,
+        }
+    }
+
+    /// Frosted glass with a GGX distribution of microfacets
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.ggx = Ggx::from_roughness(roughness);
+        self
+    }
+
+    /// Refraction index behind the surface over the one in front of it
+    fn relative_eta(&self, record: &HitRecord) -> f64 {
+        if record.front_face {
+            self.eta
+        } else {
+            1.0 / self.eta
+        }
+    }
+
+    pub fn with_normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Ray, Color)> {
-        let eta_ratio = if record.front_face {
-            1.0 / self.eta
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+        let normal = shading_normal(&self.normal_map, record);
+
+        if self.ggx.is_smooth() {
+            let unit_direction = unit_vector(ray.direction);
+            let out_direction = refract(unit_direction, normal, 1.0 / self.relative_eta(record));
+            return Some(Scatter::Specular {
+                ray: Ray::new(record.point, out_direction, ray.time),
+                attenuation: Color::WHITE,
+            });
+        }
+
+        let frame = Onb::new(normal);
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+        Some(Scatter::Pdf(Box::new(DielectricPdf {
+            frame,
+            wo,
+            ggx: self.ggx,
+            eta: self.relative_eta(record),
+        })))
+    }
+
+    fn eval(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        let frame = Onb::new(shading_normal(&self.normal_map, record));
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        let wi = frame.to_local(unit_vector(scattered.direction));
+        let eta = self.relative_eta(record);
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Color::BLACK;
+        }
+        let Some(h) = DielectricPdf::half_vector(wo, wi, eta) else {
+            return Color::BLACK;
+        };
+
+        let reflectance = microfacet::fresnel_dielectric(dot(wo, h), eta);
+        let dg = self.ggx.d(h) * self.ggx.g(wo, wi);
+        let value = if wo.z * wi.z > 0.0 {
+            dg * reflectance / (4.0 * wo.z)
         } else {
-            self.eta
+            // Radiance is compressed into the smaller solid angle of the
+            // denser side, hence the division by eta squared
+            let denom = dot(wi, h) + dot(wo, h) / eta;
+            dg * (1.0 - reflectance) * (dot(wi, h) * dot(wo, h)).abs()
+                / (wo.z * denom * denom * eta * eta)
         };
+        value * Color::WHITE
+    }
+}
+
+/// Artist friendly material layering a diffuse base, GGX specular reflection
+/// and transmission, sheen and a clearcoat, after the Disney BSDF (Burley,
+/// 2012 and 2015). Lobes are sampled in proportion to their estimated
+/// contribution.
+///
+/// Parameters follow glTF's metallic-roughness model: `base_color` is the
+/// albedo of dielectrics and the reflectance of metals, `metallic` blends
+/// between both and `roughness` is perceptually linear. `specular` scales the
+/// reflectance of dielectrics, 0.5 being the one of `ior` like glTF's
+/// default `specular_factor` of 1. Scalar parameters are read as the mean of
+/// the texture channels, see [`Channel`](crate::texture::Channel) to pick one.
+pub struct Principled {
+    base_color: ArcTexture,
+    metallic: ArcTexture,
+    roughness: ArcTexture,
+    specular: ArcTexture,
+    specular_tint: ArcTexture,
+    sheen: ArcTexture,
+    sheen_tint: ArcTexture,
+    clearcoat: ArcTexture,
+    clearcoat_roughness: ArcTexture,
+    transmission: ArcTexture,
+    /// None for surfaces that do not glow
+    emission: Option<ArcTexture>,
+    ior: f64,
+    normal_map: Option<NormalMap>,
+}
+
+fn constant(value: f64) -> ArcTexture {
+    Arc::new(SolidColor::from_rgb(value, value, value))
+}
+
+impl Principled {
+    /// Lower bound of the roughnesses, so mirror-like lobes keep a density
+    /// the integrator can weight against light sampling
+    const MIN_ROUGHNESS: f64 = 0.03;
 
-        let unit_direction = unit_vector(ray.direction);
-        let out_direction = refract(unit_direction, record.normal, eta_ratio);
+    /// Rough dielectric of the given color, the other parameters default to
+    /// the ones of glTF
+    pub fn new(base_color: ArcTexture) -> Self {
+        Self {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_roughness: constant(0.03),
+            transmission: constant(0.0),
+            emission: None,
+            ior: 1.5,
+            normal_map: None,
+        }
+    }
+
+    pub fn with_metallic(mut self, metallic: ArcTexture) -> Self {
+        self.metallic = metallic;
+        self
+    }
 
-        let attenuation = Color::WHITE;
-        Some((Ray::new(record.point, out_direction, ray.time), attenuation))
+    pub fn with_roughness(mut self, roughness: ArcTexture) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_specular(mut self, specular: ArcTexture) -> Self {
+        self.specular = specular;
+        self
+    }
+
+    /// Tints the specular reflection of dielectrics towards the base color
+    pub fn with_specular_tint(mut self, specular_tint: ArcTexture) -> Self {
+        self.specular_tint = specular_tint;
+        self
+    }
+
+    /// Retro-reflective rim of cloth, tinted towards the base color by
+    /// `sheen_tint`
+    pub fn with_sheen(mut self, sheen: ArcTexture) -> Self {
+        self.sheen = sheen;
+        self
+    }
+
+    pub fn with_sheen_tint(mut self, sheen_tint: ArcTexture) -> Self {
+        self.sheen_tint = sheen_tint;
+        self
+    }
+
+    /// Colorless varnish on top of the other lobes, with an index of 1.5
+    pub fn with_clearcoat(mut self, clearcoat: ArcTexture) -> Self {
+        self.clearcoat = clearcoat;
+        self
+    }
+
+    pub fn with_clearcoat_roughness(mut self, clearcoat_roughness: ArcTexture) -> Self {
+        self.clearcoat_roughness = clearcoat_roughness;
+        self
+    }
+
+    /// Fraction of the dielectric base refracting light instead of
+    /// diffusing it, the inside is then a glass tinted by the base color
+    pub fn with_transmission(mut self, transmission: ArcTexture) -> Self {
+        self.transmission = transmission;
+        self
+    }
+
+    pub fn with_emission(mut self, emission: ArcTexture) -> Self {
+        self.emission = Some(emission);
+        self
+    }
+
+    pub fn with_ior(mut self, ior: f64) -> Self {
+        self.ior = ior;
+        self
+    }
+
+    pub fn with_normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    /// Parameters at the point hit by `record`
+    fn lobes(&self, record: &HitRecord) -> Lobes {
+        let color =
+            |texture: &ArcTexture| texture.filtered(record.uv, record.point, record.footprint);
+        let scalar = |texture: &ArcTexture| {
+            let value = color(texture);
+            ((value.x + value.y + value.z) / 3.0).clamp(0.0, 1.0)
+        };
+
+        let base_color = color(&self.base_color);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness).max(Self::MIN_ROUGHNESS);
+        let transmission = scalar(&self.transmission);
+
+        // Hue and saturation of the base color
+        let luminance = base_color.luminance();
+        let tint = if luminance > 0.0 {
+            base_color / luminance
+        } else {
+            Color::WHITE
+        };
+        let tinted = |amount: f64| (1.0 - amount) * Color::WHITE + amount * tint;
+
+        // Inside the material only the interface of the transmission is left
+        let front = record.front_face;
+        let (diffuse, sheen, clearcoat) = if front {
+            (
+                (1.0 - metallic) * (1.0 - transmission),
+                scalar(&self.sheen) * tinted(scalar(&self.sheen_tint)),
+                scalar(&self.clearcoat),
+            )
+        } else {
+            (0.0, Color::BLACK, 0.0)
+        };
+
+        Lobes {
+            base_color,
+            roughness,
+            metallic,
+            diffuse,
+            transmission: (1.0 - metallic) * transmission,
+            // Transmission tints light both on the way in and out
+            transmittance: Color::new(
+                base_color.x.sqrt(),
+                base_color.y.sqrt(),
+                base_color.z.sqrt(),
+            ),
+            specular: 2.0 * scalar(&self.specular),
+            specular_tint: tinted(scalar(&self.specular_tint)),
+            sheen,
+            clearcoat,
+            ggx: Ggx::from_roughness(roughness),
+            clearcoat_ggx: Ggx::from_roughness(
+                scalar(&self.clearcoat_roughness).max(Self::MIN_ROUGHNESS),
+            ),
+            eta: if front { self.ior } else { 1.0 / self.ior },
+        }
+    }
+}
+
+/// Parameters of a principled material at one point and the weights of its
+/// lobes, in the local frame of the shading normal
+struct Lobes {
+    base_color: Color,
+    roughness: f64,
+    metallic: f64,
+    diffuse: f64,
+    transmission: f64,
+    transmittance: Color,
+    /// Scale of the dielectric reflectance
+    specular: f64,
+    specular_tint: Color,
+    sheen: Color,
+    clearcoat: f64,
+    ggx: Ggx,
+    clearcoat_ggx: Ggx,
+    /// Relative index of refraction, see [`DielectricPdf`]
+    eta: f64,
+}
+
+/// Schlick's weight of the Fresnel term
+fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+impl Lobes {
+    /// Reflectance of the clearcoat, an interface with an index of 1.5
+    const CLEARCOAT_F0: f64 = 0.04;
+
+    /// Reflectance of the dielectric base
+    fn dielectric_reflectance(&self, cos: f64) -> f64 {
+        (self.specular * microfacet::fresnel_dielectric(cos, self.eta)).min(1.0)
+    }
+
+    /// Reflectance of the specular lobe, blended between the dielectric and
+    /// metallic bases
+    fn reflectance(&self, cos: f64) -> Color {
+        let metal = self.base_color + (Color::WHITE - self.base_color) * schlick_weight(cos);
+        let dielectric = self.specular_tint * self.dielectric_reflectance(cos);
+        self.metallic * metal + (1.0 - self.metallic) * dielectric
+    }
+
+    /// Light left for the layers below the clearcoat
+    fn clearcoat_transmittance(&self, cos: f64) -> f64 {
+        let f0 = Self::CLEARCOAT_F0;
+        1.0 - self.clearcoat * (f0 + (1.0 - f0) * schlick_weight(cos))
+    }
+
+    /// Weight of the diffuse lobe, left with the light the dielectric
+    /// specular does not reflect like glTF's `fresnel_mix`
+    fn diffuse_weight(&self, wo: Vec3) -> f64 {
+        self.diffuse * (1.0 - self.dielectric_reflectance(wo.z))
+    }
+
+    fn eval_reflection(&self, wo: Vec3, wi: Vec3) -> Color {
+        let h = unit_vector(wo + wi);
+        let cos_d = dot(wi, h);
+
+        let specular =
+            self.reflectance(cos_d) * (self.ggx.d(h) * self.ggx.g(wo, wi) / (4.0 * wo.z));
+
+        // Burley's diffuse, with a retro-reflection growing with roughness
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let retro = |cos: f64| 1.0 + (fd90 - 1.0) * schlick_weight(cos);
+        let diffuse =
+            self.base_color * (retro(wo.z) * retro(wi.z) / PI) + self.sheen * schlick_weight(cos_d);
+
+        let f0 = Self::CLEARCOAT_F0;
+        let clearcoat = self.clearcoat
+            * (f0 + (1.0 - f0) * schlick_weight(cos_d))
+            * self.clearcoat_ggx.d(h)
+            * self.clearcoat_ggx.g(wo, wi)
+            / (4.0 * wo.z);
+
+        (diffuse * (self.diffuse_weight(wo) * wi.z) + specular) * self.clearcoat_transmittance(wo.z)
+            + clearcoat * Color::WHITE
+    }
+
+    fn eval_transmission(&self, wo: Vec3, wi: Vec3) -> Color {
+        let Some(h) = DielectricPdf::half_vector(wo, wi, self.eta) else {
+            return Color::BLACK;
+        };
+
+        let reflectance = self.dielectric_reflectance(dot(wo, h));
+        let denom = dot(wi, h) + dot(wo, h) / self.eta;
+        let value = self.ggx.d(h)
+            * self.ggx.g(wo, wi)
+            * (1.0 - reflectance)
+            * (dot(wi, h) * dot(wo, h)).abs()
+            / (wo.z * denom * denom * self.eta * self.eta);
+        self.transmittance * (self.transmission * value * self.clearcoat_transmittance(wo.z))
+    }
+
+    /// Density picking a lobe in proportion to its estimated contribution
+    fn pdf(&self, frame: Onb, wo: Vec3) -> WeightedPdf {
+        let coat = self.clearcoat_transmittance(wo.z);
+        let mut pdf = WeightedPdf::default();
+        pdf.add(
+            coat * self.diffuse_weight(wo) * (self.base_color.luminance() + self.sheen.luminance()),
+            Box::new(CosinePdf::new(frame.to_world(Vec3::new(0.0, 0.0, 1.0)))),
+        );
+        pdf.add(
+            coat * self.reflectance(wo.z).luminance(),
+            Box::new(ConductorPdf {
+                frame,
+                wo,
+                ggx: self.ggx,
+            }),
+        );
+        pdf.add(
+            coat * self.transmission
+                * (1.0 - self.dielectric_reflectance(wo.z))
+                * self.transmittance.luminance(),
+            Box::new(DielectricPdf {
+                frame,
+                wo,
+                ggx: self.ggx,
+                eta: self.eta,
+            }),
+        );
+        pdf.add(
+            1.0 - self.clearcoat_transmittance(wo.z),
+            Box::new(ConductorPdf {
+                frame,
+                wo,
+                ggx: self.clearcoat_ggx,
+            }),
+        );
+        pdf
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::new(shading_normal(&self.normal_map, record));
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let pdf = self.lobes(record).pdf(frame, wo);
+        if pdf.is_empty() {
+            return None;
+        }
+        Some(Scatter::Pdf(Box::new(pdf)))
+    }
+
+    fn eval(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        let frame = Onb::new(shading_normal(&self.normal_map, record));
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        let wi = frame.to_local(unit_vector(scattered.direction));
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Color::BLACK;
+        }
+
+        // Directions must also be on the same side of the actual surface
+        let geometric = dot(scattered.direction, record.normal);
+        let lobes = self.lobes(record);
+        if wi.z > 0.0 && geometric > 0.0 {
+            lobes.eval_reflection(wo, wi)
+        } else if wi.z < 0.0 && geometric < 0.0 {
+            lobes.eval_transmission(wo, wi)
+        } else {
+            Color::BLACK
+        }
+    }
+
+    fn emit(&self, uv: (f64, f64), point: Vec3) -> Color {
+        self.emission
+            .as_ref()
+            .map_or(Color::BLACK, |emission| emission.value(uv, point))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.emission.is_some()
     }
 }
 
@@ -137,7 +693,7 @@ pub struct DiffuseLight {
 }
 
 impl DiffuseLight {
-    pub fn _new(texture: ArcTexture) -> Self {
+    pub fn new(texture: ArcTexture) -> Self {
         Self { texture }
     }
 
@@ -147,7 +703,7 @@ impl DiffuseLight {
         }
     }
 
-    pub fn _from_color(albedo: Color) -> Self {
+    pub fn from_color(albedo: Color) -> Self {
         Self {
             texture: Arc::new(SolidColor::new(albedo)),
         }
@@ -155,13 +711,17 @@ impl DiffuseLight {
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, _: &Ray, _: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, _: &Ray, _: &HitRecord) -> Option<Scatter> {
         None
     }
 
     fn emit(&self, uv: (f64, f64), point: Vec3) -> Color {
         self.texture.value(uv, point)
     }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
 }
 
 pub struct Isotropic {
@@ -186,11 +746,49 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Ray, Color)> {
-        Some((
-            Ray::new(record.point, Vec3::random_in_unit_sphere(), ray.time),
-            self.texture.value(record.uv, record.point),
-        ))
+    fn scatter(&self, _: &Ray, _: &HitRecord) -> Option<Scatter> {
+        Some(Scatter::Pdf(Box::new(SpherePdf)))
+    }
+
+    fn eval(&self, _: &Ray, record: &HitRecord, _: &Ray) -> Color {
+        let albedo = self
+            .texture
+            .filtered(record.uv, record.point, record.footprint);
+        albedo / (4.0 * PI)
+    }
+}
+
+/// Phase function of a medium scattering light preferably forward (`g` > 0)
+/// or backward (`g` < 0), see [`pdf::henyey_greenstein`]
+pub struct HenyeyGreenstein {
+    texture: ArcTexture,
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    /// `g` is clamped to (-1, 1), the phase function is a delta at the bounds
+    pub fn new(texture: ArcTexture, g: f64) -> Self {
+        Self {
+            texture,
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray: &Ray, _: &HitRecord) -> Option<Scatter> {
+        Some(Scatter::Pdf(Box::new(HenyeyGreensteinPdf::new(
+            ray.direction,
+            self.g,
+        ))))
+    }
+
+    fn eval(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        let albedo = self
+            .texture
+            .filtered(record.uv, record.point, record.footprint);
+        let cos_theta = dot(unit_vector(ray.direction), unit_vector(scattered.direction));
+        albedo * pdf::henyey_greenstein(cos_theta, self.g)
     }
 }
 
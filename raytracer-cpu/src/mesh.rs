@@ -0,0 +1,379 @@
+use crate::{
+    boundind_box::BoundingBox,
+    bvh::{BvhStats, BvhTree},
+    hittables::{HitRecord, Hittable},
+    interval::Interval,
+    material::ArcMaterial,
+    random,
+    ray::Ray,
+    vector::{cross, dot, unit_vector, Vec3},
+};
+
+/// Watertight ray-triangle intersection (Woop, Benthin and Wald, 2013).
+/// Rays hitting a shared edge or vertex always hit one of the triangles,
+/// so there are no cracks between neighbours of a mesh.
+/// Returns the ray distance and the barycentric coordinates of the hit.
+pub fn intersect(ray: &Ray, interval: Interval, vertices: [Vec3; 3]) -> Option<(f64, [f64; 3])> {
+    let dir = ray.direction;
+
+    // Permute the axes so that z is the largest component of the direction
+    let kz = if dir.x.abs() > dir.y.abs() {
+        if dir.x.abs() > dir.z.abs() {
+            0
+        } else {
+            2
+        }
+    } else if dir.y.abs() > dir.z.abs() {
+        1
+    } else {
+        2
+    };
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    // Keep the winding order of the triangle
+    if dir[kz] < 0.0 {
+        (kx, ky) = (ky, kx);
+    }
+
+    // Shear the space so the ray points along +z
+    let sx = dir[kx] / dir[kz];
+    let sy = dir[ky] / dir[kz];
+    let sz = 1.0 / dir[kz];
+
+    let [a, b, c] = vertices.map(|v| v - ray.origin);
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    // Scaled barycentric coordinates
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let t = sz * (u * a[kz] + v * b[kz] + w * c[kz]) / det;
+    if !interval.surrounds(t) {
+        return None;
+    }
+
+    Some((t, [u / det, v / det, w / det]))
+}
+
+/// Uniform random point on a triangle
+fn sample_triangle(vertices: [Vec3; 3]) -> Vec3 {
+    let r1 = random::float().sqrt();
+    let r2 = random::float();
+    let [a, b, c] = vertices;
+
+    (1.0 - r1) * a + r1 * (1.0 - r2) * b + r1 * r2 * c
+}
+
+fn triangle_bbox(vertices: [Vec3; 3]) -> BoundingBox {
+    let [a, b, c] = vertices;
+    BoundingBox::from_boxes(
+        BoundingBox::from_extrema(a, b),
+        BoundingBox::from_extrema(a, c),
+    )
+}
+
+/// Convert the area density 1 / `area` at the point hit by `record` into a
+/// solid angle density as seen along `direction`
+fn area_to_solid_angle(record: &HitRecord, direction: Vec3, area: f64) -> f64 {
+    let distance_squared = record.distance * record.distance * direction.len_squared();
+    let cosine = (dot(direction, record.normal) / direction.len()).abs();
+
+    distance_squared / (cosine * area)
+}
+
+/// Single triangle with flat shading
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    normal: Vec3,
+    area: f64,
+    material: ArcMaterial,
+    bbox: BoundingBox,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3, material: ArcMaterial) -> Self {
+        let n = cross(b - a, c - a);
+        Self {
+            vertices: [a, b, c],
+            normal: unit_vector(n),
+            area: 0.5 * n.len(),
+            material,
+            bbox: triangle_bbox([a, b, c]),
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let (t, [_, b1, b2]) = intersect(ray, interval, self.vertices)?;
+        let [a, b, c] = self.vertices;
+
+        // Barycentric coordinates cover half of the unit square
+        Some(
+            HitRecord::new(ray, self.normal, (b1, b2), t, self.material.clone())
+                .with_tangents(b - a, c - a)
+                .with_uv_density((0.5 / self.area).sqrt()),
+        )
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.bbox.clone()
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
+        let ray = Ray::new(origin, direction, time);
+        match self.hit(&ray, Interval::positive()) {
+            Some(record) => area_to_solid_angle(&record, direction, self.area),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: Vec3, _: f64) -> Vec3 {
+        sample_triangle(self.vertices) - origin
+    }
+}
+
+/// Triangle of a mesh, stored as indices into the mesh buffers
+#[derive(Debug, Clone, Copy)]
+pub struct Face {
+    pub positions: [u32; 3],
+    pub normals: Option<[u32; 3]>,
+    pub uvs: Option<[u32; 3]>,
+    /// Index into the materials of the mesh
+    pub material: u32,
+}
+
+/// Indexed triangle mesh with shared vertex buffers, optional per-vertex
+/// normals and texture coordinates, and its own BVH over the faces.
+pub struct TriangleMesh {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<Face>,
+    materials: Vec<ArcMaterial>,
+    bvh: BvhTree,
+    /// Cumulative face areas, used to sample points on the mesh
+    area_cdf: Vec<f64>,
+}
+
+impl TriangleMesh {
+    /// Build a mesh. Panics if a face refers to a missing vertex, normal,
+    /// texture coordinate or material.
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        faces: Vec<Face>,
+        materials: Vec<ArcMaterial>,
+    ) -> Self {
+        for face in faces.iter() {
+            assert!(face
+                .positions
+                .iter()
+                .all(|&i| (i as usize) < positions.len()));
+            assert!(face
+                .normals
+                .is_none_or(|n| n.iter().all(|&i| (i as usize) < normals.len())));
+            assert!(face
+                .uvs
+                .is_none_or(|uv| uv.iter().all(|&i| (i as usize) < uvs.len())));
+            assert!((face.material as usize) < materials.len());
+        }
+
+        let bboxes: Vec<_> = faces
+            .iter()
+            .map(|face| triangle_bbox(face.positions.map(|i| positions[i as usize])))
+            .collect();
+        let (bvh, order) = BvhTree::build(&bboxes);
+        let faces: Vec<Face> = order.into_iter().map(|i| faces[i]).collect();
+
+        let mut area = 0.0;
+        let area_cdf = faces
+            .iter()
+            .map(|face| {
+                let [a, b, c] = face.positions.map(|i| positions[i as usize]);
+                area += 0.5 * cross(b - a, c - a).len();
+                area
+            })
+            .collect();
+
+        Self {
+            positions,
+            normals,
+            uvs,
+            faces,
+            materials,
+            bvh,
+            area_cdf,
+        }
+    }
+
+    /// Use the same material for every face
+    pub fn with_material(mut self, material: ArcMaterial) -> Self {
+        self.materials = vec![material];
+        self.faces.iter_mut().for_each(|face| face.material = 0);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        self.bvh.stats()
+    }
+
+    /// Mesh of the faces with an emissive material, to sample as a light.
+    /// None if no face glows.
+    pub fn emissive(&self) -> Option<TriangleMesh> {
+        let faces: Vec<Face> = self
+            .faces
+            .iter()
+            .filter(|face| self.materials[face.material as usize].is_emissive())
+            .copied()
+            .collect();
+        if faces.is_empty() {
+            return None;
+        }
+        Some(Self::new(
+            self.positions.clone(),
+            self.normals.clone(),
+            self.uvs.clone(),
+            faces,
+            self.materials.clone(),
+        ))
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area_cdf.last().copied().unwrap_or_default()
+    }
+
+    fn vertices(&self, face: &Face) -> [Vec3; 3] {
+        face.positions.map(|i| self.positions[i as usize])
+    }
+
+    fn hit_face(&self, ray: &Ray, interval: Interval, face: &Face) -> Option<HitRecord> {
+        let vertices = self.vertices(face);
+        let (t, barycentric) = intersect(ray, interval, vertices)?;
+        let [b0, b1, b2] = barycentric;
+        let [a, b, c] = vertices;
+        let n = cross(b - a, c - a);
+        let geometric = unit_vector(n);
+
+        // Interpolated normals are kept on the side of the geometric one
+        let normal = match face.normals {
+            Some(indices) => {
+                let [n0, n1, n2] = indices.map(|i| self.normals[i as usize]);
+                let normal = unit_vector(b0 * n0 + b1 * n1 + b2 * n2);
+                if dot(normal, geometric) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                }
+            }
+            None => geometric,
+        };
+
+        // Without texture coordinates the barycentric ones are used. The
+        // areas are twice the area of the face in texture and world space.
+        let (dp1, dp2) = (b - a, c - a);
+        let (uv, tangents, uv_area) = match face.uvs {
+            Some(indices) => {
+                let [uv0, uv1, uv2] = indices.map(|i| self.uvs[i as usize]);
+                let uv = (
+                    b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+                    b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+                );
+
+                // Solve dp1 = du1 ∂p/∂u + dv1 ∂p/∂v and the same for dp2
+                let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+                let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+                let det = du1 * dv2 - du2 * dv1;
+                let tangents = if det.abs() > 1e-12 {
+                    ((dv2 * dp1 - dv1 * dp2) / det, (du1 * dp2 - du2 * dp1) / det)
+                } else {
+                    (dp1, dp2)
+                };
+                (uv, tangents, det.abs())
+            }
+            None => ((b1, b2), (dp1, dp2), 1.0),
+        };
+        let world_area = n.len();
+        let uv_density = if world_area > 0.0 {
+            (uv_area / world_area).sqrt()
+        } else {
+            0.0
+        };
+
+        let material = self.materials[face.material as usize].clone();
+        Some(
+            HitRecord::new(ray, normal, uv, t, material)
+                .with_tangents(tangents.0, tangents.1)
+                .with_uv_density(uv_density),
+        )
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.bvh.hit(ray, interval, |i, interval| {
+            self.hit_face(ray, interval, &self.faces[i])
+        })
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.bvh.bounding_box()
+    }
+
+    // Every intersection along the direction could have been sampled
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
+        let area = self.area();
+        if area <= 0.0 {
+            return 0.0;
+        }
+
+        let ray = Ray::new(origin, direction, time);
+        let mut interval = Interval::positive();
+        let mut pdf = 0.0;
+        while let Some(record) = self.hit(&ray, interval) {
+            pdf += area_to_solid_angle(&record, direction, area);
+            interval.min = record.distance * (1.0 + 1e-9) + 1e-9;
+        }
+
+        pdf
+    }
+
+    // Faces are picked proportionally to their area
+    fn random(&self, origin: Vec3, _: f64) -> Vec3 {
+        if self.faces.is_empty() {
+            return Vec3::X;
+        }
+
+        let target = random::float() * self.area();
+        let i = self
+            .area_cdf
+            .partition_point(|&area| area < target)
+            .min(self.faces.len() - 1);
+
+        sample_triangle(self.vertices(&self.faces[i])) - origin
+    }
+}
@@ -0,0 +1,223 @@
+//! GGX (Trowbridge-Reitz) microfacet model.
+//!
+//! Directions are in a local shading frame where the normal is +z, `wo`
+//! points towards the viewer and `wi` towards the light. Masking and
+//! shadowing use the height correlated Smith function and directions are
+//! sampled from the distribution of normals visible from `wo` (Heitz, 2018).
+
+use std::f64::consts::PI;
+
+use crate::{
+    onb::Onb,
+    pdf::Pdf,
+    random,
+    vector::{cross, dot, unit_vector, Vec3},
+};
+
+/// Isotropic GGX distribution of microfacet normals
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    /// Below this the surface is treated as perfectly smooth
+    const SMOOTH_ALPHA: f64 = 1e-3;
+
+    /// `roughness` is perceptually linear in [0, 1], the width of the
+    /// distribution is its square
+    pub fn from_roughness(roughness: f64) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self {
+            alpha: roughness * roughness,
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < Self::SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normals, projected onto the macro surface
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let denom = h.z * h.z * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denom * denom)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    /// Fraction of the microfacets facing `w` that are visible from it
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets visible from both directions
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `wo`
+    pub fn visible_d(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * dot(wo, h).abs() * self.d(h) / wo.z.abs()
+    }
+
+    /// Sample a normal visible from `wo`, which must be above the surface
+    pub fn sample_visible_normal(&self, wo: Vec3) -> Vec3 {
+        // Stretch to the configuration of a unit hemisphere
+        let vh = unit_vector(Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z));
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / len2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(vh, t1);
+
+        // Uniform point on a disk, warped onto the visible half
+        let r = random::float().sqrt();
+        let phi = 2.0 * PI * random::float();
+        let (p1, p2) = (r * phi.cos(), r * phi.sin());
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * p2;
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        unit_vector(Vec3::new(
+            self.alpha * nh.x,
+            self.alpha * nh.y,
+            nh.z.max(1e-6),
+        ))
+    }
+}
+
+/// Fresnel reflectance of a dielectric interface, `eta` is the ratio of
+/// the refraction index on the other side over the one on the side of the
+/// normal. Both polarizations are averaged, no absorption.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / eta)
+    } else {
+        (cos_i, eta)
+    };
+    let cos_i = cos_i.min(1.0);
+
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// Mirror `w` around `n`, both pointing away from the surface
+pub fn reflect(w: Vec3, n: Vec3) -> Vec3 {
+    2.0 * dot(w, n) * n - w
+}
+
+/// Refract `w`, pointing away from the surface on the side of `n`, through
+/// an interface with relative index `eta`. None on total internal reflection.
+pub fn refract(w: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = dot(w, n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-w / eta + (cos_i / eta - cos_t) * n)
+}
+
+/// Reflection off a rough conductor
+pub struct ConductorPdf {
+    pub frame: Onb,
+    pub wo: Vec3,
+    pub ggx: Ggx,
+}
+
+impl Pdf for ConductorPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let wi = self.frame.to_local(unit_vector(direction));
+        if wi.z <= 0.0 {
+            return 0.0;
+        }
+        let h = unit_vector(self.wo + wi);
+        self.ggx.visible_d(self.wo, h) / (4.0 * dot(self.wo, h).abs())
+    }
+
+    fn generate(&self) -> Vec3 {
+        let h = self.ggx.sample_visible_normal(self.wo);
+        self.frame.to_world(reflect(self.wo, h))
+    }
+}
+
+/// Reflection and transmission through a rough dielectric, `eta` is the
+/// relative index of the side the normal points away from
+pub struct DielectricPdf {
+    pub frame: Onb,
+    pub wo: Vec3,
+    pub ggx: Ggx,
+    pub eta: f64,
+}
+
+impl DielectricPdf {
+    /// Microfacet normal scattering `wo` into `wi`, facing `wo`. Returns
+    /// None for configurations a single microfacet cannot produce.
+    pub fn half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+        let reflection = wo.z * wi.z > 0.0;
+        let h = if reflection { wo + wi } else { wo + eta * wi };
+        if h.len_squared() == 0.0 {
+            return None;
+        }
+        let h = unit_vector(h);
+        let h = if h.z < 0.0 { -h } else { h };
+
+        // Discard back facing microfacets
+        if dot(h, wi) * wi.z < 0.0 || dot(h, wo) * wo.z < 0.0 {
+            return None;
+        }
+        Some(h)
+    }
+}
+
+impl Pdf for DielectricPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let wi = self.frame.to_local(unit_vector(direction));
+        let Some(h) = Self::half_vector(self.wo, wi, self.eta) else {
+            return 0.0;
+        };
+
+        let reflectance = fresnel_dielectric(dot(self.wo, h), self.eta);
+        let visible = self.ggx.visible_d(self.wo, h);
+        if self.wo.z * wi.z > 0.0 {
+            return visible / (4.0 * dot(self.wo, h).abs()) * reflectance;
+        }
+
+        let denom = dot(wi, h) + dot(self.wo, h) / self.eta;
+        visible * dot(wi, h).abs() / (denom * denom) * (1.0 - reflectance)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let h = self.ggx.sample_visible_normal(self.wo);
+        let reflectance = fresnel_dielectric(dot(self.wo, h), self.eta);
+        let wi = match refract(self.wo, h, self.eta) {
+            Some(wi) if random::float() >= reflectance => wi,
+            _ => reflect(self.wo, h),
+        };
+        self.frame.to_world(wi)
+    }
+}
@@ -0,0 +1,90 @@
+//! Surface detail added by perturbing the shading normal.
+//!
+//! Materials only change the normal they scatter around, the geometry and
+//! the geometric normal used for intersections stay untouched.
+
+use crate::{
+    hittables::HitRecord,
+    texture::ArcTexture,
+    vector::{cross, dot, unit_vector, Vec3},
+};
+
+pub enum NormalMap {
+    /// Tangent space normals stored in the texture, with the components
+    /// mapped from [-1, 1] to [0, 1]. `strength` scales the tangential part,
+    /// 0 keeps the surface flat.
+    Tangent { texture: ArcTexture, strength: f64 },
+    /// Height field moving the surface along its normal, only its slope
+    /// matters. Heights are the mean of the texture channels times `scale`.
+    Bump { height: ArcTexture, scale: f64 },
+}
+
+impl NormalMap {
+    /// Shading normal at the point hit by `record`, on the same side as the
+    /// normal of the record
+    pub fn normal(&self, record: &HitRecord) -> Vec3 {
+        let normal = match self {
+            Self::Tangent { texture, strength } => Self::tangent_space(record, texture, *strength),
+            Self::Bump { height, scale } => Self::bump(record, height, *scale),
+        };
+
+        match normal {
+            Some(normal) if dot(normal, record.normal) > 0.0 => normal,
+            Some(normal) => -normal,
+            None => record.normal,
+        }
+    }
+
+    fn tangent_space(record: &HitRecord, texture: &ArcTexture, strength: f64) -> Option<Vec3> {
+        let (tangent, bitangent) = tangent_frame(record)?;
+        let value = texture.filtered(record.uv, record.point, record.footprint);
+        let [x, y, z] = [value.x, value.y, value.z].map(|c| 2.0 * c - 1.0);
+
+        let normal = strength * (x * tangent + y * bitangent) + z * record.normal;
+        (normal.len_squared() > 0.0).then(|| unit_vector(normal))
+    }
+
+    /// Normal of the displaced surface p + h n, with the height derivatives
+    /// estimated by finite differences along the texture coordinates
+    fn bump(record: &HitRecord, height: &ArcTexture, scale: f64) -> Option<Vec3> {
+        let (dpdu, dpdv) = (record.tangent, record.bitangent);
+        if dpdu.len_squared() == 0.0 || dpdv.len_squared() == 0.0 {
+            return None;
+        }
+
+        let h = |du: f64, dv: f64| {
+            let uv = (record.uv.0 + du, record.uv.1 + dv);
+            let point = record.point + du * dpdu + dv * dpdv;
+            let value = height.filtered(uv, point, record.footprint);
+            scale * (value.x + value.y + value.z) / 3.0
+        };
+
+        // Differences over the footprint of the ray, so finer detail than a
+        // pixel does not turn into noise
+        let delta = (0.5 * record.footprint).max(1e-4);
+        let h0 = h(0.0, 0.0);
+        let dhdu = (h(delta, 0.0) - h0) / delta;
+        let dhdv = (h(0.0, delta) - h0) / delta;
+
+        let normal = cross(dpdu + dhdu * record.normal, dpdv + dhdv * record.normal);
+        (normal.len_squared() > 0.0).then(|| unit_vector(normal))
+    }
+}
+
+/// Orthonormal tangent and bitangent around the normal of `record`, keeping
+/// the orientation of ∂p/∂v. None when the surface has no parametrization.
+fn tangent_frame(record: &HitRecord) -> Option<(Vec3, Vec3)> {
+    let normal = record.normal;
+    // Interpolated normals are not perpendicular to ∂p/∂u
+    let tangent = record.tangent - dot(record.tangent, normal) * normal;
+    if tangent.len_squared() < 1e-24 {
+        return None;
+    }
+    let tangent = unit_vector(tangent);
+
+    let bitangent = cross(normal, tangent);
+    if dot(bitangent, record.bitangent) < 0.0 {
+        return Some((tangent, -bitangent));
+    }
+    Some((tangent, bitangent))
+}
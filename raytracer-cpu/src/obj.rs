@@ -0,0 +1,474 @@
+use std::{collections::HashMap, fmt, fs, io, path::Path, str::FromStr, sync::Arc};
+
+use crate::{
+    color::Color,
+    material::{ArcMaterial, Conductor, Dielectric, DiffuseLight, Lambertian, Principled},
+    mesh::{Face, TriangleMesh},
+    normal_map::NormalMap,
+    texture::{ArcTexture, ImageTexture, SolidColor},
+    vector::Vec3,
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(String, io::Error),
+    Parse {
+        path: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "could not read '{path}': {e}"),
+            Self::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "in '{path}', line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Load a Wavefront OBJ file as a single triangle mesh.
+///
+/// Polygons are split into triangle fans. Materials come from the MTL files
+/// listed with `mtllib`, faces without `usemtl` or using a material that is
+/// not defined get `default_material`.
+pub fn load(path: &str, default_material: ArcMaterial) -> Result<TriangleMesh, ObjError> {
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_string(), e))?;
+    from_str(&source, path, default_material)
+}
+
+/// Parse the content of an OBJ file, MTL files are looked up next to `path`
+pub fn from_str(
+    source: &str,
+    path: &str,
+    default_material: ArcMaterial,
+) -> Result<TriangleMesh, ObjError> {
+    let mut parser = Parser {
+        path,
+        line: 0,
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        faces: Vec::new(),
+        library: HashMap::new(),
+        materials: vec![default_material],
+        material_indices: HashMap::new(),
+        material: 0,
+    };
+
+    for (i, line) in source.lines().enumerate() {
+        parser.line = i + 1;
+        parser.parse_line(line)?;
+    }
+
+    Ok(TriangleMesh::new(
+        parser.positions,
+        parser.normals,
+        parser.uvs,
+        parser.faces,
+        parser.materials,
+    ))
+}
+
+/// Path of a file referenced by `path`, relative to its directory
+fn sibling(path: &str, file: &str) -> String {
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    dir.join(file).to_string_lossy().into_owned()
+}
+
+fn parse_error(path: &str, line: usize, message: String) -> ObjError {
+    ObjError::Parse {
+        path: path.to_string(),
+        line,
+        message,
+    }
+}
+
+fn parse_floats<const N: usize>(
+    args: &[&str],
+    optional: usize,
+    path: &str,
+    line: usize,
+) -> Result<[f64; N], ObjError> {
+    if args.len() < N - optional {
+        return Err(parse_error(
+            path,
+            line,
+            format!("expected {} numbers, found {}", N - optional, args.len()),
+        ));
+    }
+
+    let mut values = [0.0; N];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = f64::from_str(arg)
+            .map_err(|_| parse_error(path, line, format!("invalid number '{arg}'")))?;
+    }
+    Ok(values)
+}
+
+struct Parser<'a> {
+    path: &'a str,
+    line: usize,
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<Face>,
+    /// Materials of every loaded MTL file, by name
+    library: HashMap<String, ArcMaterial>,
+    /// Materials used by the mesh, the first one is the default material
+    materials: Vec<ArcMaterial>,
+    material_indices: HashMap<String, u32>,
+    /// Material of the faces that follow
+    material: u32,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: String) -> ObjError {
+        parse_error(self.path, self.line, message)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), ObjError> {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            return Ok(());
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats(&args, 0, self.path, self.line)?;
+                self.positions.push(Vec3::new(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats(&args, 0, self.path, self.line)?;
+                self.normals.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v] = parse_floats(&args, 1, self.path, self.line)?;
+                self.uvs.push((u, v));
+            }
+            "f" => self.face(&args)?,
+            "usemtl" => {
+                let name = args.join(" ");
+                self.material = self.material_index(&name);
+            }
+            "mtllib" => {
+                for file in args {
+                    let mtl_path = sibling(self.path, file);
+                    self.library.extend(load_mtl(&mtl_path)?);
+                }
+            }
+            // Groups, objects, smoothing groups, lines and points
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn material_index(&mut self, name: &str) -> u32 {
+        if let Some(&index) = self.material_indices.get(name) {
+            return index;
+        }
+
+        let Some(material) = self.library.get(name) else {
+            return 0;
+        };
+        let index = self.materials.len() as u32;
+        self.materials.push(material.clone());
+        self.material_indices.insert(name.to_string(), index);
+        index
+    }
+
+    // OBJ indices start at 1, negative ones count back from the last element
+    fn index(&self, token: &str, count: usize) -> Result<u32, ObjError> {
+        let index: i64 = token
+            .parse()
+            .map_err(|_| self.error(format!("invalid index '{token}'")))?;
+
+        let resolved = match index {
+            1.. => index - 1,
+            ..=-1 => count as i64 + index,
+            0 => -1,
+        };
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!("index {index} out of range")));
+        }
+        Ok(resolved as u32)
+    }
+
+    fn face(&mut self, args: &[&str]) -> Result<(), ObjError> {
+        if args.len() < 3 {
+            return Err(self.error(format!(
+                "a face needs at least 3 vertices, found {}",
+                args.len()
+            )));
+        }
+
+        // Vertices are `v`, `v/vt`, `v//vn` or `v/vt/vn`
+        let mut vertices = Vec::with_capacity(args.len());
+        for arg in args {
+            let mut parts = arg.split('/');
+            let position = self.index(parts.next().unwrap_or_default(), self.positions.len())?;
+            let uv = match parts.next() {
+                Some(token) if !token.is_empty() => Some(self.index(token, self.uvs.len())?),
+                _ => None,
+            };
+            let normal = match parts.next() {
+                Some(token) if !token.is_empty() => Some(self.index(token, self.normals.len())?),
+                _ => None,
+            };
+            vertices.push((position, uv, normal));
+        }
+
+        // Attributes are only used if every vertex of the face has them
+        let has_uvs = vertices.iter().all(|v| v.1.is_some());
+        let has_normals = vertices.iter().all(|v| v.2.is_some());
+
+        let first = vertices[0];
+        for pair in vertices[1..].windows(2) {
+            let corners = [first, pair[0], pair[1]];
+            self.faces.push(Face {
+                positions: corners.map(|v| v.0),
+                uvs: has_uvs.then(|| corners.map(|v| v.1.unwrap_or_default())),
+                normals: has_normals.then(|| corners.map(|v| v.2.unwrap_or_default())),
+                material: self.material,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Subset of the MTL parameters we can map onto our materials
+struct MtlDesc {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: f64,
+    refraction_index: f64,
+    dissolve: f64,
+    illum: u32,
+    diffuse_map: Option<String>,
+    normal_map: Option<String>,
+    /// Height map with its `-bm` multiplier
+    bump_map: Option<(String, f64)>,
+    /// Physically based extension, the material is principled when any of
+    /// these is given
+    roughness: Option<f64>,
+    metallic: Option<f64>,
+    sheen: Option<f64>,
+    clearcoat: Option<f64>,
+    clearcoat_roughness: Option<f64>,
+    roughness_map: Option<String>,
+    metallic_map: Option<String>,
+}
+
+impl Default for MtlDesc {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::BLACK,
+            emission: Color::BLACK,
+            shininess: 0.0,
+            refraction_index: 1.5,
+            dissolve: 1.0,
+            illum: 2,
+            diffuse_map: None,
+            normal_map: None,
+            bump_map: None,
+            roughness: None,
+            metallic: None,
+            sheen: None,
+            clearcoat: None,
+            clearcoat_roughness: None,
+            roughness_map: None,
+            metallic_map: None,
+        }
+    }
+}
+
+impl MtlDesc {
+    fn normal_map(&self) -> Option<NormalMap> {
+        if let Some(path) = &self.normal_map {
+            return Some(NormalMap::Tangent {
+                texture: Arc::new(ImageTexture::open(path).raw()),
+                strength: 1.0,
+            });
+        }
+
+        self.bump_map.as_ref().map(|(path, scale)| NormalMap::Bump {
+            height: Arc::new(ImageTexture::open(path).raw()),
+            scale: *scale,
+        })
+    }
+
+    fn is_principled(&self) -> bool {
+        [
+            self.roughness,
+            self.metallic,
+            self.sheen,
+            self.clearcoat,
+            self.clearcoat_roughness,
+        ]
+        .iter()
+        .any(Option::is_some)
+            || self.roughness_map.is_some()
+            || self.metallic_map.is_some()
+    }
+
+    fn principled(&self) -> Principled {
+        let constant = |value: f64| -> ArcTexture {
+            Arc::new(SolidColor::new(Color::new(value, value, value)))
+        };
+        let map = |path: &Option<String>, value: Option<f64>, default: f64| -> ArcTexture {
+            match path {
+                Some(path) => Arc::new(ImageTexture::open(path).raw()),
+                None => constant(value.unwrap_or(default)),
+            }
+        };
+
+        let base_color: ArcTexture = match &self.diffuse_map {
+            Some(path) => Arc::new(ImageTexture::open(path)),
+            None => Arc::new(SolidColor::new(self.diffuse)),
+        };
+        let material = Principled::new(base_color)
+            .with_roughness(map(&self.roughness_map, self.roughness, 0.5))
+            .with_metallic(map(&self.metallic_map, self.metallic, 0.0))
+            .with_sheen(constant(self.sheen.unwrap_or(0.0)))
+            .with_clearcoat(constant(self.clearcoat.unwrap_or(0.0)))
+            .with_clearcoat_roughness(constant(self.clearcoat_roughness.unwrap_or(0.03)))
+            .with_transmission(constant(1.0 - self.dissolve))
+            .with_ior(self.refraction_index);
+        let material = if self.emission.x.max(self.emission.y).max(self.emission.z) > 0.0 {
+            material.with_emission(Arc::new(SolidColor::new(self.emission)))
+        } else {
+            material
+        };
+        match self.normal_map() {
+            Some(normal_map) => material.with_normal_map(normal_map),
+            None => material,
+        }
+    }
+
+    fn material(&self) -> ArcMaterial {
+        let is_black = |c: Color| c.x.max(c.y).max(c.z) <= 0.0;
+
+        if self.is_principled() {
+            return Arc::new(self.principled());
+        }
+
+        if !is_black(self.emission) {
+            return Arc::new(DiffuseLight::from_color(self.emission));
+        }
+
+        let normal_map = self.normal_map();
+
+        // Illumination models 4, 6, 7 and 9 are the transparent ones
+        if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            let material = Dielectric::new(self.refraction_index);
+            return match normal_map {
+                Some(normal_map) => Arc::new(material.with_normal_map(normal_map)),
+                None => Arc::new(material),
+            };
+        }
+
+        if self.illum == 3 || (is_black(self.diffuse) && !is_black(self.specular)) {
+            // Usual match of a Phong exponent to a microfacet width, the
+            // roughness is its square root
+            let alpha = (2.0 / (self.shininess + 2.0)).sqrt();
+            let material = Conductor::new(self.specular, alpha.sqrt());
+            return match normal_map {
+                Some(normal_map) => Arc::new(material.with_normal_map(normal_map)),
+                None => Arc::new(material),
+            };
+        }
+
+        let material = match &self.diffuse_map {
+            Some(path) => Lambertian::new(Arc::new(ImageTexture::open(path))),
+            None => Lambertian::from_albedo(self.diffuse),
+        };
+        match normal_map {
+            Some(normal_map) => Arc::new(material.with_normal_map(normal_map)),
+            None => Arc::new(material),
+        }
+    }
+}
+
+/// Load the materials of an MTL file by name
+fn load_mtl(path: &str) -> Result<HashMap<String, ArcMaterial>, ObjError> {
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_string(), e))?;
+
+    let mut descs: Vec<(String, MtlDesc)> = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            descs.push((args.join(" "), MtlDesc::default()));
+            continue;
+        }
+
+        let Some((_, desc)) = descs.last_mut() else {
+            return Err(parse_error(
+                path,
+                line_number,
+                format!("`{keyword}` before any `newmtl`"),
+            ));
+        };
+        let float = |args: &[&str]| parse_floats::<1>(args, 0, path, line_number).map(|[x]| x);
+        let color = |args: &[&str]| {
+            parse_floats::<3>(args, 0, path, line_number).map(|[r, g, b]| Color::new(r, g, b))
+        };
+
+        match keyword {
+            "Kd" => desc.diffuse = color(&args)?,
+            "Ks" => desc.specular = color(&args)?,
+            "Ke" => desc.emission = color(&args)?,
+            "Ns" => desc.shininess = float(&args)?,
+            "Ni" => desc.refraction_index = float(&args)?,
+            "d" => desc.dissolve = float(&args)?,
+            "Tr" => desc.dissolve = 1.0 - float(&args)?,
+            "illum" => {
+                desc.illum = args.first().and_then(|s| s.parse().ok()).ok_or_else(|| {
+                    parse_error(path, line_number, "invalid illumination model".to_string())
+                })?
+            }
+            // Options such as `-s 1 1 1` come before the file name
+            "map_Kd" => desc.diffuse_map = args.last().map(|file| sibling(path, file)),
+            "Pr" => desc.roughness = Some(float(&args)?),
+            "Pm" => desc.metallic = Some(float(&args)?),
+            "Ps" => desc.sheen = Some(float(&args)?),
+            "Pc" => desc.clearcoat = Some(float(&args)?),
+            "Pcr" => desc.clearcoat_roughness = Some(float(&args)?),
+            "map_Pr" => desc.roughness_map = args.last().map(|file| sibling(path, file)),
+            "map_Pm" => desc.metallic_map = args.last().map(|file| sibling(path, file)),
+            "norm" => desc.normal_map = args.last().map(|file| sibling(path, file)),
+            "bump" | "map_Bump" | "map_bump" => {
+                let scale = match args.iter().position(|&arg| arg == "-bm") {
+                    Some(i) => float(&args[i + 1..])?,
+                    None => 1.0,
+                };
+                desc.bump_map = args.last().map(|file| (sibling(path, file), scale));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(descs
+        .into_iter()
+        .map(|(name, desc)| (name, desc.material()))
+        .collect())
+}
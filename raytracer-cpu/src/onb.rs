@@ -0,0 +1,36 @@
+//! Orthonormal basis around a direction, to sample directions in a local
+//! frame where that direction is +z and bring them back to world space.
+
+use crate::vector::{dot, unit_vector, Vec3};
+
+/// Orthonormal basis with `w` along a given direction (Duff et al., 2017)
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: Vec3) -> Self {
+        let w = unit_vector(w);
+        let sign = 1.0f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+        Self {
+            u: Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vec3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        }
+    }
+
+    /// Coordinates of a world space vector in the basis
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(dot(v, self.u), dot(v, self.v), dot(v, self.w))
+    }
+
+    /// World space vector from its coordinates in the basis
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x * self.u + v.y * self.v + v.z * self.w
+    }
+}
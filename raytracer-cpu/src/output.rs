@@ -0,0 +1,270 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::color::{to_rgb16, to_rgb8, Color};
+
+/// Encodes a rendered image. Pixels are linear radiance values stored row by
+/// row starting from the top left corner.
+pub trait ImageWriter {
+    fn write(
+        &self,
+        out: &mut dyn Write,
+        width: usize,
+        height: usize,
+        pixels: &[Color],
+    ) -> io::Result<()>;
+}
+
+/// Settings of the image formats that have any, e.g. the bit depth of PNG
+/// images
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SaveOptions {
+    /// Write PNG images with 16 bits per channel instead of 8
+    pub sixteen_bit: bool,
+}
+
+/// Pick an image writer based on the extension of `path`
+pub fn writer_for_path(path: &str) -> Option<Box<dyn ImageWriter>> {
+    writer_with(path, &SaveOptions::default())
+}
+
+/// Same as [`writer_for_path`], configured with `options`
+pub fn writer_with(path: &str, options: &SaveOptions) -> Option<Box<dyn ImageWriter>> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+
+    match extension.as_str() {
+        "ppm" => Some(Box::new(Ppm)),
+        "png" => Some(Box::new(Png {
+            sixteen_bit: options.sixteen_bit,
+        })),
+        "hdr" => Some(Box::new(Hdr)),
+        "pfm" => Some(Box::new(Pfm)),
+        _ => None,
+    }
+}
+
+/// Write the image to `path` in the format matching its extension
+pub fn save(path: &str, width: usize, height: usize, pixels: &[Color]) -> io::Result<()> {
+    save_with(path, width, height, pixels, &SaveOptions::default())
+}
+
+/// Same as [`save`], with the settings of the format in `options`
+pub fn save_with(
+    path: &str,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+    options: &SaveOptions,
+) -> io::Result<()> {
+    let writer = writer_with(path, options).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported output format '{path}', expected .ppm, .png, .hdr or .pfm"),
+        )
+    })?;
+
+    let mut out = BufWriter::new(File::create(path)?);
+    writer.write(&mut out, width, height, pixels)?;
+    out.flush()
+}
+
+/// Binary (P6) portable pixmap, gamma corrected and clamped to 8 bits
+pub struct Ppm;
+
+impl ImageWriter for Ppm {
+    fn write(
+        &self,
+        out: &mut dyn Write,
+        width: usize,
+        height: usize,
+        pixels: &[Color],
+    ) -> io::Result<()> {
+        write!(out, "P6\n{width} {height}\n255\n")?;
+
+        let bytes: Vec<u8> = pixels.iter().flat_map(|&c| to_rgb8(c)).collect();
+        out.write_all(&bytes)
+    }
+}
+
+/// Truecolor PNG, gamma corrected and clamped to either 8 or 16 bits
+pub struct Png {
+    pub sixteen_bit: bool,
+}
+
+impl Png {
+    // Largest payload of a single uncompressed deflate block
+    const MAX_STORED_BLOCK: usize = 65535;
+
+    fn write_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+        out.write_all(&(data.len() as u32).to_be_bytes())?;
+        out.write_all(kind)?;
+        out.write_all(data)?;
+
+        let crc = crc32(kind.iter().chain(data));
+        out.write_all(&crc.to_be_bytes())
+    }
+
+    // Wrap `data` in a zlib stream made of stored (uncompressed) deflate blocks
+    fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let blocks = data.len().div_ceil(Self::MAX_STORED_BLOCK).max(1);
+        let mut stream = Vec::with_capacity(data.len() + 5 * blocks + 6);
+
+        // CMF and FLG: deflate with 32K window, no preset dictionary
+        stream.extend_from_slice(&[0x78, 0x01]);
+
+        let mut chunks = data.chunks(Self::MAX_STORED_BLOCK).peekable();
+        if chunks.peek().is_none() {
+            stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        }
+        while let Some(chunk) = chunks.next() {
+            let is_final = chunks.peek().is_none();
+            let len = chunk.len() as u16;
+
+            stream.push(is_final as u8);
+            stream.extend_from_slice(&len.to_le_bytes());
+            stream.extend_from_slice(&(!len).to_le_bytes());
+            stream.extend_from_slice(chunk);
+        }
+
+        stream.extend_from_slice(&adler32(data).to_be_bytes());
+        stream
+    }
+}
+
+impl ImageWriter for Png {
+    fn write(
+        &self,
+        out: &mut dyn Write,
+        width: usize,
+        height: usize,
+        pixels: &[Color],
+    ) -> io::Result<()> {
+        out.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+
+        let bit_depth = if self.sixteen_bit { 16 } else { 8 };
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        // bit depth, truecolor, deflate, no filtering, no interlacing
+        header.extend_from_slice(&[bit_depth, 2, 0, 0, 0]);
+        Self::write_chunk(out, b"IHDR", &header)?;
+
+        let mut raw = Vec::new();
+        for row in pixels.chunks(width.max(1)) {
+            // Filter type "None" for every scanline
+            raw.push(0);
+            for &color in row {
+                if self.sixteen_bit {
+                    raw.extend(to_rgb16(color).iter().flat_map(|c| c.to_be_bytes()));
+                } else {
+                    raw.extend(to_rgb8(color));
+                }
+            }
+        }
+
+        Self::write_chunk(out, b"IDAT", &Self::zlib_stored(&raw))?;
+        Self::write_chunk(out, b"IEND", &[])
+    }
+}
+
+/// Radiance RGBE image, keeps the linear unclamped radiance
+pub struct Hdr;
+
+impl Hdr {
+    fn rgbe(color: Color) -> [u8; 4] {
+        let max = color.x.max(color.y).max(color.z);
+        if max < 1e-32 || !max.is_finite() {
+            return [0, 0, 0, 0];
+        }
+
+        // max = mantissa * 2^exponent, with mantissa in [0.5, 1)
+        let exponent = max.log2().floor() as i32 + 1;
+        let scale = 256.0 / 2f64.powi(exponent);
+        let channel = |c: f64| (c.max(0.0) * scale).min(255.0) as u8;
+
+        [
+            channel(color.x),
+            channel(color.y),
+            channel(color.z),
+            (exponent + 128).clamp(0, 255) as u8,
+        ]
+    }
+}
+
+impl ImageWriter for Hdr {
+    fn write(
+        &self,
+        out: &mut dyn Write,
+        width: usize,
+        height: usize,
+        pixels: &[Color],
+    ) -> io::Result<()> {
+        write!(
+            out,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {height} +X {width}\n"
+        )?;
+
+        // Flat (non run-length encoded) scanlines are valid for every reader
+        let bytes: Vec<u8> = pixels.iter().flat_map(|&c| Self::rgbe(c)).collect();
+        out.write_all(&bytes)
+    }
+}
+
+/// Portable float map, keeps the linear unclamped radiance as 32-bit floats
+pub struct Pfm;
+
+impl ImageWriter for Pfm {
+    fn write(
+        &self,
+        out: &mut dyn Write,
+        width: usize,
+        height: usize,
+        pixels: &[Color],
+    ) -> io::Result<()> {
+        // Negative scale marks little endian data
+        write!(out, "PF\n{width} {height}\n-1.0\n")?;
+
+        // Scanlines are stored bottom to top
+        let mut bytes = Vec::with_capacity(pixels.len() * 12);
+        for row in pixels.chunks(width.max(1)).rev() {
+            for color in row {
+                for c in [color.x, color.y, color.z] {
+                    bytes.extend_from_slice(&(c as f32).to_le_bytes());
+                }
+            }
+        }
+        out.write_all(&bytes)
+    }
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+
+    // 5552 is the largest block that cannot overflow before the modulo
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+
+    (b << 16) | a
+}
@@ -0,0 +1,194 @@
+use std::f64::consts::PI;
+
+use crate::{
+    hittables::Hittable,
+    onb::Onb,
+    random,
+    vector::{unit_vector, Vec3},
+};
+
+/// Probability density over directions, with respect to solid angle
+pub trait Pdf {
+    fn value(&self, direction: Vec3) -> f64;
+    fn generate(&self) -> Vec3;
+}
+
+/// Uniform density over all directions
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+}
+
+/// Henyey-Greenstein phase function of a participating medium, for the
+/// angle between the direction the light was travelling in and the one it
+/// is scattered to. `g` is the mean of that cosine: positive values scatter
+/// forward, negative ones back and 0 in every direction alike.
+pub fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+/// Density of the Henyey-Greenstein phase function around the direction
+/// of the incoming light
+pub struct HenyeyGreensteinPdf {
+    frame: Onb,
+    g: f64,
+}
+
+impl HenyeyGreensteinPdf {
+    pub fn new(direction: Vec3, g: f64) -> Self {
+        Self {
+            frame: Onb::new(unit_vector(direction)),
+            g,
+        }
+    }
+}
+
+impl Pdf for HenyeyGreensteinPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cos_theta = self.frame.to_local(unit_vector(direction)).z;
+        henyey_greenstein(cos_theta, self.g)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let g = self.g;
+        let u = random::float();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let phi = 2.0 * PI * random::float();
+        self.frame.to_world(Vec3::from_spherical(cos_theta, phi))
+    }
+}
+
+/// Density proportional to the cosine with the surface normal
+pub struct CosinePdf {
+    onb: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: Vec3) -> Self {
+        Self {
+            onb: Onb::new(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine = self.onb.to_local(unit_vector(direction)).z;
+        cosine.max(0.0) / PI
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.onb.to_world(Vec3::random_cosine_direction())
+    }
+}
+
+/// Density of directions pointing from `origin` towards `objects` at `time`,
+/// used to sample lights explicitly
+pub struct HittablePdf<'a, T: Hittable + ?Sized> {
+    objects: &'a T,
+    origin: Vec3,
+    time: f64,
+}
+
+impl<'a, T: Hittable + ?Sized> HittablePdf<'a, T> {
+    pub fn new(objects: &'a T, origin: Vec3, time: f64) -> Self {
+        Self {
+            objects,
+            origin,
+            time,
+        }
+    }
+}
+
+impl<T: Hittable + ?Sized> Pdf for HittablePdf<'_, T> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.objects.pdf_value(self.origin, direction, self.time)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.objects.random(self.origin, self.time)
+    }
+}
+
+/// Equal mix of two densities
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(first: &'a dyn Pdf, second: &'a dyn Pdf) -> Self {
+        Self {
+            pdfs: [first, second],
+        }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        0.5 * self.pdfs[0].value(direction) + 0.5 * self.pdfs[1].value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        if random::float() < 0.5 {
+            return self.pdfs[0].generate();
+        }
+        self.pdfs[1].generate()
+    }
+}
+
+/// Mix of densities picked in proportion to their weights, e.g. the lobes of
+/// a layered material
+#[derive(Default)]
+pub struct WeightedPdf {
+    pdfs: Vec<(f64, Box<dyn Pdf>)>,
+    total: f64,
+}
+
+impl WeightedPdf {
+    /// Densities without a positive weight are left out
+    pub fn add(&mut self, weight: f64, pdf: Box<dyn Pdf>) {
+        if weight > 0.0 {
+            self.pdfs.push((weight, pdf));
+            self.total += weight;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pdfs.is_empty()
+    }
+}
+
+impl Pdf for WeightedPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let sum: f64 = self
+            .pdfs
+            .iter()
+            .map(|(weight, pdf)| weight * pdf.value(direction))
+            .sum();
+        sum / self.total
+    }
+
+    fn generate(&self) -> Vec3 {
+        let mut target = random::float() * self.total;
+        for (weight, pdf) in self.pdfs.iter() {
+            if target < *weight {
+                return pdf.generate();
+            }
+            target -= weight;
+        }
+        self.pdfs.last().map_or(Vec3::X, |(_, pdf)| pdf.generate())
+    }
+}
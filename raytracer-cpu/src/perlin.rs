@@ -1,5 +1,5 @@
 use crate::{
-    random,
+    random::Generator,
     utils::lerp,
     vector::{dot, unit_vector, Vec3},
 };
@@ -12,19 +12,22 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new(point_count: usize) -> Self {
+    /// Noise of `point_count` random gradients, picked from `seed`
+    pub fn new(point_count: usize, seed: u64) -> Self {
+        let mut rng = Generator::new(seed);
         // let rand_float = (0..point_count).map(|_| random::float()).collect();
         let rand_vecs = (0..point_count)
-            .map(|_| unit_vector(Vec3::random_min_max(-1.0, 1.0)))
+            .map(|_| unit_vector(rng.vec3(-1.0, 1.0)))
             .collect();
 
         Self {
             // floats: rand_float,
             rand_vecs,
-            perm: Self::generate_perm(point_count),
+            perm: Self::generate_perm(point_count, &mut rng),
         }
     }
 
+    #[allow(clippy::needless_range_loop)]
     pub fn noise(&self, point: Vec3) -> f64 {
         let u = point.x - (point.x).floor();
         let v = point.y - (point.y).floor();
@@ -63,6 +66,23 @@ impl Perlin {
         accum.abs()
     }
 
+    /// Fractal Brownian motion: `octaves` layers of noise, each one twice
+    /// the frequency and half the amplitude of the previous one
+    pub fn fbm(&self, point: Vec3, octaves: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut weight = 1.0;
+        let mut point = point;
+
+        for _ in 0..octaves {
+            accum += weight * self.noise(point);
+            weight *= 0.5;
+            point = 2.0 * point;
+        }
+
+        accum
+    }
+
+    #[allow(clippy::needless_range_loop)]
     fn trilinear_interp(c: [[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
         // Hermitian smoothing
         let u = Self::hermitian_fade(u);
@@ -91,14 +111,14 @@ impl Perlin {
         x * x * (3.0 - 2.0 * x)
     }
 
-    fn generate_perm(point_count: usize) -> Vec<usize> {
+    fn generate_perm(point_count: usize, rng: &mut Generator) -> Vec<usize> {
         let mut perm: Vec<usize> = (0..point_count).collect();
         // use rand::seq::SliceRandom;
         // let mut rng = rand::thread_rng();
         // perm.shuffle(&mut rng);
 
         for i in (1..point_count).rev() {
-            perm.swap(i, random::usize(0, i));
+            perm.swap(i, rng.usize(0, i));
         }
 
         perm
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+/// Snapshot of how far along a render is
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    /// Number of camera samples computed so far
+    pub samples_done: u64,
+    pub samples_total: u64,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Completed fraction of the render in [0, 1]
+    pub fn fraction(&self) -> f64 {
+        if self.samples_total == 0 {
+            return 1.0;
+        }
+        self.samples_done as f64 / self.samples_total as f64
+    }
+
+    pub fn samples_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return 0.0;
+        }
+        self.samples_done as f64 / secs
+    }
+
+    /// Estimated time remaining, assuming the current sampling rate holds
+    pub fn eta(&self) -> Option<Duration> {
+        let rate = self.samples_per_sec();
+        if rate <= 0.0 {
+            return None;
+        }
+
+        let remaining = self.samples_total.saturating_sub(self.samples_done);
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    }
+}
+
+/// Receives progress updates while rendering. Updates are sent from the
+/// render threads, typically once per finished tile.
+pub trait ProgressReporter: Sync {
+    fn update(&self, progress: &Progress);
+
+    #[allow(unused_variables)]
+    fn finish(&self, progress: &Progress) {}
+}
+
+/// Reporter that ignores every update
+pub struct Silent;
+
+impl ProgressReporter for Silent {
+    fn update(&self, _: &Progress) {}
+}
@@ -1,10 +1,11 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
     boundind_box::BoundingBox,
     hittables::{ArcHittable, HitRecord, Hittable, HittableList},
     interval::Interval,
     material::{ArcMaterial, Material},
+    random,
     ray::Ray,
     vector::{cross, dot, unit_vector, Vec3, EPS},
 };
@@ -22,6 +23,7 @@ pub struct Quad {
     d: f64,
     w: Vec3,
     normal: Vec3,
+    area: f64,
     material: Arc<dyn Material + Send + Sync>,
     bbox: BoundingBox,
     shape: Shape,
@@ -42,8 +44,14 @@ impl Quad {
         // lies inside the quad
         let w = n / n.len_squared();
 
+        let area = match shape {
+            Shape::Square => n.len(),
+            Shape::Ellipsis => PI * n.len(),
+            Shape::Triangle => 0.5 * n.len(),
+        };
+
         let bbox = Self::set_bbox(origin, u, v);
-        Self { origin, u, v, d, w, normal, bbox, material , shape  }
+        Self { origin, u, v, d, w, normal, area, bbox, material , shape  }
     }
 
     fn set_bbox(o: Vec3, u: Vec3, v: Vec3) -> BoundingBox {
@@ -96,18 +104,51 @@ impl Hittable for Quad {
 
         let uv = self.get_uv(ray.at(root))?;
 
-        Some(HitRecord::new(
-            ray,
-            self.normal,
-            uv,
-            root,
-            self.material.clone(),
-        ))
+        // The unit square of texture coordinates covers |u x v| = 1 / |w|
+        Some(
+            HitRecord::new(ray, self.normal, uv, root, self.material.clone())
+                .with_tangents(self.u, self.v)
+                .with_uv_density(self.w.len().sqrt()),
+        )
     }
 
     fn bounding_box(&self) -> BoundingBox {
         self.bbox.clone()
     }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
+        let ray = Ray::new(origin, direction, time);
+        let Some(record) = self.hit(&ray, Interval::positive()) else {
+            return 0.0;
+        };
+
+        // Convert the uniform area density to solid angle
+        let distance_squared = record.distance * record.distance * direction.len_squared();
+        let cosine = (dot(direction, self.normal) / direction.len()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Vec3, _: f64) -> Vec3 {
+        let (alpha, beta) = match self.shape {
+            Shape::Square => (random::float(), random::float()),
+            Shape::Ellipsis => {
+                let p = Vec3::random_in_unit_disk();
+                (p.x, p.y)
+            }
+            Shape::Triangle => {
+                let (a, b) = (random::float(), random::float());
+                // Fold the other half of the parallelogram back in
+                if a + b > 1.0 {
+                    (1.0 - a, 1.0 - b)
+                } else {
+                    (a, b)
+                }
+            }
+        };
+
+        self.origin + alpha * self.u + beta * self.v - origin
+    }
 }
 
 pub fn create_box(a: Vec3, b: Vec3, material: ArcMaterial) -> ArcHittable {
@@ -1,19 +1,147 @@
-use rand::random;
-use rand::{thread_rng, Rng};
+//! Seedable random number generation.
+//!
+//! Every thread owns a generator that all the helpers below draw from. The
+//! renderer reseeds it before each camera sample with a seed derived from
+//! (seed, pixel, sample), so images do not depend on how rayon schedules the
+//! work. Scene construction and Perlin noise draw from a [`Generator`] of
+//! their own instead, seeded explicitly, so they do not depend on the thread
+//! building them either.
+//!
+//! While a camera sample is traced, [`float`] draws from the [`Sampler`] of
+//! the camera instead, one dimension after the other within the window
+//! opened with [`dimensions`]. Past the window it falls back to the
+//! generator.
+
+use std::cell::RefCell;
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 use rand_distr::StandardNormal;
 
+use crate::{sampler::ArcSampler, vector::Vec3};
+
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(0));
+    static STREAM: RefCell<Option<Stream>> = const { RefCell::new(None) };
+}
+
+/// Sample of a pixel being drawn from a sampler
+struct Stream {
+    sampler: ArcSampler,
+    pixel: (u32, u32),
+    index: u32,
+    /// Next dimension and the end of the window
+    dimension: u32,
+    end: u32,
+}
+
+impl Stream {
+    fn next(&mut self) -> Option<f64> {
+        if self.dimension >= self.end {
+            return None;
+        }
+        let value = self.sampler.get(self.pixel, self.index, self.dimension);
+        self.dimension += 1;
+        Some(value)
+    }
+}
+
+/// Draw the numbers of sample `index` of `pixel` from `sampler`, in the
+/// windows opened with [`dimensions`]
+pub fn start_sample(sampler: &ArcSampler, pixel: (u32, u32), index: u32) {
+    STREAM.with(|stream| {
+        *stream.borrow_mut() = Some(Stream {
+            sampler: sampler.clone(),
+            pixel,
+            index,
+            dimension: 0,
+            end: 0,
+        })
+    });
+}
+
+/// Go back to the generator alone
+pub fn end_sample() {
+    STREAM.with(|stream| *stream.borrow_mut() = None);
+}
+
+/// Draw the next `count` numbers from dimensions `first..first + count` of
+/// the current sample. Giving every part of a path its own dimensions keeps
+/// them aligned between the samples of a pixel, whatever the other parts
+/// drew.
+pub fn dimensions(first: u32, count: u32) {
+    STREAM.with(|stream| {
+        if let Some(stream) = stream.borrow_mut().as_mut() {
+            stream.dimension = first;
+            stream.end = first + count;
+        }
+    });
+}
+
+/// Restart the random stream of the current thread from `seed`
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+/// Derive the seed of an independent stream, e.g. for one sample of one pixel
+pub fn derive(seed: u64, pixel: u64, sample: u64) -> u64 {
+    mix(mix(mix(seed) ^ pixel) ^ sample)
+}
+
+// SplitMix64 finalizer, spreads small input differences over all bits
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 pub fn float() -> f64 {
-    random()
+    if let Some(value) = STREAM.with(|stream| stream.borrow_mut().as_mut()?.next()) {
+        return value;
+    }
+    RNG.with(|rng| rng.borrow_mut().gen())
 }
 
 pub fn usize(min: usize, max: usize) -> usize {
-    thread_rng().gen_range(min..max)
+    let value = min + (float() * (max - min) as f64) as usize;
+    value.min(max - 1)
 }
 
 pub fn normal() -> f64 {
-    thread_rng().sample(StandardNormal)
+    RNG.with(|rng| rng.borrow_mut().sample(StandardNormal))
 }
 
 pub fn in_interval(min: f64, max: f64) -> f64 {
-    min + (max - min) * random::<f64>()
+    min + (max - min) * float()
+}
+
+/// Generator owned by its user rather than the thread, for what must only
+/// depend on its seed
+pub struct Generator(SmallRng);
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self(SmallRng::seed_from_u64(seed))
+    }
+
+    pub fn float(&mut self) -> f64 {
+        self.0.gen()
+    }
+
+    pub fn usize(&mut self, min: usize, max: usize) -> usize {
+        let value = min + (self.float() * (max - min) as f64) as usize;
+        value.min(max - 1)
+    }
+
+    pub fn in_interval(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.float()
+    }
+
+    pub fn vec3(&mut self, min: f64, max: f64) -> Vec3 {
+        Vec3::new(
+            self.in_interval(min, max),
+            self.in_interval(min, max),
+            self.in_interval(min, max),
+        )
+    }
 }
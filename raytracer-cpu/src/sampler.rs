@@ -0,0 +1,452 @@
+//! Sample patterns for the random numbers of a path.
+//!
+//! A sampler gives the coordinate `dimension` of sample `index` of a pixel,
+//! one dimension per random number drawn along the path: pixel position,
+//! lens, time, then the light and direction samples of every bounce (see
+//! [`random::dimensions`]). Independent random numbers converge slowly,
+//! spreading the samples of a pixel more evenly in every dimension lowers the
+//! noise for the same number of samples.
+
+use std::{
+    fmt::{self, Debug},
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+
+use crate::random;
+
+pub trait Sampler: Debug + Send + Sync {
+    /// Coordinate `dimension`, in [0, 1), of sample `index` of the pixel at
+    /// column `x` and row `y`
+    fn get(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f64;
+}
+
+pub type ArcSampler = Arc<dyn Sampler>;
+
+/// Samplers that can be picked from the command line or a scene file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub const NAMES: [&'static str; 5] =
+        ["independent", "stratified", "halton", "sobol", "blue_noise"];
+
+    /// Sampler for images taking `samples` samples per pixel
+    pub fn build(self, samples: u32, seed: u64) -> ArcSampler {
+        match self {
+            Self::Independent => Arc::new(Independent::new(seed)),
+            Self::Stratified => Arc::new(Stratified::new(samples, seed)),
+            Self::Halton => Arc::new(Halton::new(seed)),
+            Self::Sobol => Arc::new(Sobol::new(seed)),
+            Self::BlueNoise => Arc::new(BlueNoise::new(seed)),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "independent" => Ok(Self::Independent),
+            "stratified" => Ok(Self::Stratified),
+            "halton" => Ok(Self::Halton),
+            "sobol" => Ok(Self::Sobol),
+            "blue_noise" => Ok(Self::BlueNoise),
+            _ => Err(format!(
+                "unknown sampler '{s}', expected one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Uniform random numbers, every one independent of the others
+#[derive(Debug)]
+pub struct Independent {
+    seed: u64,
+}
+
+impl Independent {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl Sampler for Independent {
+    fn get(&self, (x, y): (u32, u32), index: u32, dimension: u32) -> f64 {
+        let pixel = hash(self.seed, x as u64, y as u64);
+        to_float(hash(pixel, index as u64, dimension as u64))
+    }
+}
+
+/// Jittered sampling: pairs of dimensions are split in a grid of as many
+/// cells as there are samples per pixel, each sample falling randomly in
+/// its own cell. Samples past the count start a new round of cells.
+#[derive(Debug)]
+pub struct Stratified {
+    /// Cells along the first and second dimension of a pair
+    grid: (u32, u32),
+    seed: u64,
+}
+
+impl Stratified {
+    pub fn new(samples: u32, seed: u64) -> Self {
+        // Most square grid with exactly one cell per sample
+        let samples = samples.max(1);
+        let columns = (1..=samples.isqrt())
+            .rev()
+            .find(|&c| samples.is_multiple_of(c))
+            .unwrap_or(1);
+        Self {
+            grid: (columns, samples / columns),
+            seed,
+        }
+    }
+}
+
+impl Sampler for Stratified {
+    fn get(&self, (x, y): (u32, u32), index: u32, dimension: u32) -> f64 {
+        let (columns, rows) = self.grid;
+        let cells = columns * rows;
+        let round = index / cells;
+
+        // Cells are visited in a different order for every pair of dimensions
+        let pixel = hash(self.seed, x as u64, y as u64);
+        let pair = hash(pixel, (dimension / 2) as u64, round as u64);
+        let cell = permute(index % cells, cells, pair as u32);
+
+        let jitter = to_float(hash(pixel, index as u64, dimension as u64));
+        if dimension.is_multiple_of(2) {
+            ((cell % columns) as f64 + jitter) / columns as f64
+        } else {
+            ((cell / columns) as f64 + jitter) / rows as f64
+        }
+    }
+}
+
+/// Halton sequence, the radical inverse of the sample index in a different
+/// prime base for every dimension, shifted by a random offset per pixel so
+/// pixels do not repeat the same pattern
+#[derive(Debug)]
+pub struct Halton {
+    seed: u64,
+}
+
+impl Halton {
+    /// Dimensions past this use independent random numbers, the sequence is
+    /// poorly distributed in large bases
+    const DIMENSIONS: usize = 128;
+
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    fn primes() -> &'static [u32] {
+        static PRIMES: OnceLock<Vec<u32>> = OnceLock::new();
+        PRIMES.get_or_init(|| {
+            let mut primes = Vec::with_capacity(Self::DIMENSIONS);
+            let mut n = 2;
+            while primes.len() < Self::DIMENSIONS {
+                if primes
+                    .iter()
+                    .take_while(|&&p| p * p <= n)
+                    .all(|p| n % p != 0)
+                {
+                    primes.push(n);
+                }
+                n += 1;
+            }
+            primes
+        })
+    }
+}
+
+impl Sampler for Halton {
+    fn get(&self, (x, y): (u32, u32), index: u32, dimension: u32) -> f64 {
+        let pixel = hash(self.seed, x as u64, y as u64);
+        let Some(&base) = Self::primes().get(dimension as usize) else {
+            return to_float(hash(pixel, index as u64, dimension as u64));
+        };
+
+        // Cranley-Patterson rotation
+        let offset = to_float(hash(pixel, dimension as u64, u64::MAX));
+        let value = radical_inverse(index, base) + offset;
+        (value - value.floor()).min(ONE_MINUS_EPSILON)
+    }
+}
+
+/// Sobol sequence with Owen scrambling. The first two dimensions of the
+/// Sobol sequence, whose every power of two prefix is stratified in 2D, are
+/// reused for every pair of dimensions with their own scrambling and order
+/// of the samples (Burley, "Practical Hash-based Owen Scrambling", 2020).
+#[derive(Debug)]
+pub struct Sobol {
+    seed: u64,
+}
+
+impl Sobol {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl Sampler for Sobol {
+    fn get(&self, (x, y): (u32, u32), index: u32, dimension: u32) -> f64 {
+        let pixel = hash(self.seed, x as u64, y as u64);
+        owen_sobol(pixel, index, dimension)
+    }
+}
+
+/// Same scrambled Sobol samples in every pixel, each pixel shifting them by
+/// the values of a blue noise mask. Neighbouring pixels get very different
+/// shifts, so their errors look like high frequency noise, which is less
+/// visible and easier to filter than white noise (Georgiev and Fajardo,
+/// "Blue-noise Dithered Sampling", 2016).
+pub struct BlueNoise {
+    seed: u64,
+    mask: &'static [f64],
+}
+
+impl Debug for BlueNoise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlueNoise")
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
+
+impl BlueNoise {
+    /// Width and height of the mask, which repeats over the image
+    const SIZE: usize = 64;
+
+    pub fn new(seed: u64) -> Self {
+        static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+        Self {
+            seed,
+            mask: MASK.get_or_init(|| void_and_cluster(Self::SIZE)),
+        }
+    }
+}
+
+impl Sampler for BlueNoise {
+    fn get(&self, (x, y): (u32, u32), index: u32, dimension: u32) -> f64 {
+        // Each dimension reads the mask at a different offset, so their
+        // shifts are not correlated
+        let offset = hash(self.seed, dimension as u64, u64::MAX);
+        let size = Self::SIZE as u64;
+        let mx = (x as u64 + offset % size) % size;
+        let my = (y as u64 + (offset >> 32) % size) % size;
+        let shift = self.mask[(my * size + mx) as usize];
+
+        let value = owen_sobol(self.seed, index, dimension) + shift;
+        (value - value.floor()).min(ONE_MINUS_EPSILON)
+    }
+}
+
+/// Largest f64 below 1
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+fn hash(seed: u64, a: u64, b: u64) -> u64 {
+    random::derive(seed, a, b)
+}
+
+/// Uniform value in [0, 1) from the high bits of a hash
+fn to_float(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Bits of `index` in base `base` mirrored around the radix point
+fn radical_inverse(mut index: u32, base: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed = 0u64;
+    let mut scale = 1.0;
+    while index > 0 {
+        reversed = reversed * base as u64 + (index % base) as u64;
+        scale *= inverse_base;
+        index /= base;
+    }
+    (reversed as f64 * scale).min(ONE_MINUS_EPSILON)
+}
+
+/// Dimension `dimension` of the Owen scrambled, shuffled padded Sobol
+/// sequence seeded with `seed`
+fn owen_sobol(seed: u64, index: u32, dimension: u32) -> f64 {
+    let pair = hash(seed, (dimension / 2) as u64, 0);
+    // Both dimensions of a pair go through the samples in the same order
+    let index = nested_uniform_scramble(index, pair as u32);
+    let sobol = if dimension.is_multiple_of(2) {
+        index.reverse_bits()
+    } else {
+        sobol_second_dimension(index)
+    };
+    let scrambled = nested_uniform_scramble(sobol, (pair >> 32) as u32 ^ dimension);
+    scrambled as f64 / (1u64 << 32) as f64
+}
+
+/// Second dimension of the Sobol sequence, as the 32 bits after the radix
+/// point. The first one is the index with its bits reversed.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut direction = 1u32 << 31;
+    let mut value = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            value ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    value
+}
+
+/// Owen scrambling of the bits of `x` after the radix point: every bit is
+/// flipped depending on the bits before it
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Hash in which every bit only depends on the lower bits (Burley, 2020)
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x ^= x.wrapping_mul(0x3d20adea);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x05526c56);
+    x ^= x.wrapping_mul(0x53a22864);
+    x
+}
+
+/// Element `i` of a random permutation of `0..len` picked by `seed`
+/// (Kensler, "Correlated Multi-Jittered Sampling", 2013)
+fn permute(mut i: u32, len: u32, seed: u32) -> u32 {
+    if len <= 1 {
+        return 0;
+    }
+
+    // Bijection over the next power of two, repeated until it lands in range
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            return (i.wrapping_add(seed)) % len;
+        }
+    }
+}
+
+/// Blue noise mask of `size` × `size` values in (0, 1), ranking the pixels
+/// in the order the void and cluster method fills them (Ulichney, 1993):
+/// every new pixel goes where the pattern so far leaves the largest gap
+fn void_and_cluster(size: usize) -> Vec<f64> {
+    let n = size * size;
+    let sigma: f64 = 1.9;
+
+    // Gaussian energy of a pixel on the others, on a torus so the mask tiles
+    let kernel: Vec<f64> = (0..n)
+        .map(|i| {
+            let wrap = |d: usize| d.min(size - d) as f64;
+            let (dx, dy) = (wrap(i % size), wrap(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let energy_at = |from: usize, to: usize| {
+        let dx = (to % size + size - from % size) % size;
+        let dy = (to / size + size - from / size) % size;
+        kernel[dy * size + dx]
+    };
+    let update = |energy: &mut [f64], pixel: usize, sign: f64| {
+        for (to, e) in energy.iter_mut().enumerate() {
+            *e += sign * energy_at(pixel, to);
+        }
+    };
+    let extreme = |energy: &[f64], on: &[bool], value: bool, largest: bool| {
+        let candidates = (0..n).filter(|&i| on[i] == value);
+        if largest {
+            candidates.max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        } else {
+            candidates.min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        }
+        .unwrap()
+    };
+
+    // Random initial pattern covering a tenth of the pixels
+    let mut on = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let initial = n / 10;
+    let mut placed = 0;
+    let mut k = 0;
+    while placed < initial {
+        let i = (hash(0x5eed, k, 0) % n as u64) as usize;
+        k += 1;
+        if !on[i] {
+            on[i] = true;
+            update(&mut energy, i, 1.0);
+            placed += 1;
+        }
+    }
+
+    // Move the point of the tightest cluster into the largest void until
+    // that does not change anything
+    for _ in 0..n {
+        let cluster = extreme(&energy, &on, true, true);
+        on[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = extreme(&energy, &on, false, false);
+        on[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; n];
+
+    // Rank the initial points by removing the tightest clusters first
+    let (mut pattern, mut pattern_energy) = (on.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = extreme(&pattern_energy, &pattern, true, true);
+        pattern[cluster] = false;
+        update(&mut pattern_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    // Then fill the largest voids with the others. Past half of the pixels
+    // this is the same as removing the tightest clusters of empty pixels.
+    for r in initial..n {
+        let void = extreme(&energy, &on, false, false);
+        on[void] = true;
+        update(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.into_iter()
+        .map(|r| (r as f64 + 0.5) / n as f64)
+        .collect()
+}
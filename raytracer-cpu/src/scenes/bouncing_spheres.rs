@@ -1,20 +1,22 @@
 use std::sync::Arc;
 
 use crate::{
-    boundind_box::BvhNode,
-    camera::{Camera, CameraConfig},
+    bvh::Bvh,
+    camera::CameraConfig,
     color::Color,
     hittables::HittableList,
     material::{Dielectric, Lambertian, Metal},
-    random,
+    random::Generator,
+    scenes::Scene,
+    sky::Sky,
     sphere::Sphere,
     texture::Checker,
     vector::Vec3,
 };
 
-pub fn bouncing_spheres() {
+pub fn bouncing_spheres(seed: u64) -> Scene {
     // image_width, samples, and max_depth are the big performance hitter
-    let camera = Camera::new(CameraConfig {
+    let camera = CameraConfig {
         aspect_ratio: 16.0 / 9.0,
         image_width: 800,
         samples: 50,
@@ -22,8 +24,14 @@ pub fn bouncing_spheres() {
         vfov: 20.0,
         look_from: Vec3::new(13.0, 2.0, 3.0),
         defocus_angle: 0.6,
+        // Afternoon sun behind the camera
+        environment: Some(Arc::new(Sky::new(35.0, 120.0, 3.0))),
+        // The moving spheres bounce over one second, as in the book the
+        // shutter stays open for all of it
+        fps: 1.0,
+        shutter_close: 1.0,
         ..CameraConfig::default()
-    });
+    };
 
     let checker = Checker::from_colors(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
     let ground_material = Lambertian::new(Arc::new(checker));
@@ -34,22 +42,23 @@ pub fn bouncing_spheres() {
     ))]);
 
     let dielectric = Arc::new(Dielectric::new(1.5));
+    let mut rng = Generator::new(seed);
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = random::float();
+            let choose_mat = rng.float();
             let center = Vec3::new(
-                a as f64 + 0.9 * random::float(),
+                a as f64 + 0.9 * rng.float(),
                 0.2,
-                b as f64 + 0.9 * random::float(),
+                b as f64 + 0.9 * rng.float(),
             );
 
             if (center - Vec3::new(4.0, 0.2, 0.0)).len() > 0.9 {
                 // Diffuse
                 if choose_mat < 0.8 {
-                    let albedo = Color::random() * Color::random();
+                    let albedo = rng.vec3(0.0, 1.0) * rng.vec3(0.0, 1.0);
                     let material = Arc::new(Lambertian::from_albedo(albedo));
-                    let new_center = center + Vec3::new(0.0, random::in_interval(0.0, 0.5), 0.0);
+                    let new_center = center + Vec3::new(0.0, rng.in_interval(0.0, 0.5), 0.0);
                     world.add(Arc::new(Sphere::new_in_motion(
                         center, new_center, 0.2, material,
                     )));
@@ -57,8 +66,8 @@ pub fn bouncing_spheres() {
                 }
                 // Metal
                 if choose_mat < 0.95 {
-                    let albedo = Color::random_min_max(0.5, 1.0);
-                    let fuzz = random::in_interval(0.0, 0.5);
+                    let albedo = rng.vec3(0.5, 1.0);
+                    let fuzz = rng.in_interval(0.0, 0.5);
                     let material = Arc::new(Metal::new(albedo, fuzz));
                     world.add(Arc::new(Sphere::new(center, 0.2, material)));
                     continue;
@@ -87,9 +96,11 @@ pub fn bouncing_spheres() {
         Arc::new(Metal::from_rgb((0.7, 0.6, 0.5), 0.0)),
     )));
 
-    let world = BvhNode::from(world);
+    let world = Bvh::from(world);
 
-    if let Err(e) = camera.render(world) {
-        eprintln!("Failed while rendering with error: {e}")
+    Scene {
+        camera,
+        world: Arc::new(world),
+        lights: HittableList::new(),
     }
 }
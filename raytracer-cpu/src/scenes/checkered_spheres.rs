@@ -1,17 +1,12 @@
 use std::sync::Arc;
 
 use crate::{
-    camera::{Camera, CameraConfig},
-    color::Color,
-    hittables::HittableList,
-    material::Lambertian,
-    sphere::Sphere,
-    texture::Checker,
-    vector::Vec3,
+    camera::CameraConfig, color::Color, hittables::HittableList, material::Lambertian,
+    scenes::Scene, sphere::Sphere, texture::Checker, vector::Vec3,
 };
 
-pub fn checkered_spheres() {
-    let camera = Camera::new(CameraConfig {
+pub fn checkered_spheres() -> Scene {
+    let camera = CameraConfig {
         aspect_ratio: 16.0 / 9.0,
         image_width: 800,
         samples: 100,
@@ -19,7 +14,7 @@ pub fn checkered_spheres() {
         vfov: 20.0,
         look_from: Vec3::new(13.0, 2.0, 3.0),
         ..CameraConfig::default()
-    });
+    };
 
     let checker = Arc::new(Checker::from_colors(
         0.32,
@@ -37,7 +32,9 @@ pub fn checkered_spheres() {
         Arc::new(Sphere::new(Vec3::new(0.0, 10.0, 0.0), 10.0, material)),
     ]);
 
-    if let Err(e) = camera.render(world) {
-        eprintln!("Failed while rendering with error: {e}")
+    Scene {
+        camera,
+        world: Arc::new(world),
+        lights: HittableList::new(),
     }
 }
@@ -1,17 +1,19 @@
 use std::sync::Arc;
 
 use crate::{
-    boundind_box::BvhNode,
-    camera::{Camera, CameraConfig},
+    bvh::Bvh,
+    camera::CameraConfig,
     color::Color,
-    hittables::{HittableList, RotateY, Translate},
+    hittables::{ArcHittable, HittableList, Transform},
     material::{DiffuseLight, Lambertian},
+    matrix::Mat4,
     quad::{create_box, Quad, Shape},
+    scenes::Scene,
     vector::Vec3,
 };
 
-pub fn cornell_box() {
-    let camera = Camera::new(CameraConfig {
+pub fn cornell_box() -> Scene {
+    let camera = CameraConfig {
         aspect_ratio: 1.0,
         image_width: 800,
         samples: 200,
@@ -21,23 +23,24 @@ pub fn cornell_box() {
         look_from: Vec3::new(278.0, 278.0, -800.0),
         look_at: Vec3::new(278.0, 278.0, 0.0),
         ..CameraConfig::default()
-    });
+    };
 
     let red = Arc::new(Lambertian::from_rgb(0.65, 0.05, 0.05));
     let white = Arc::new(Lambertian::from_rgb(0.73, 0.73, 0.73));
     let green = Arc::new(Lambertian::from_rgb(0.12, 0.45, 0.15));
     let light = Arc::new(DiffuseLight::from_rgb(15.0, 15.0, 15.0));
 
+    let light_quad: ArcHittable = Arc::new(Quad::new(
+        Vec3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light,
+        Shape::Square,
+    ));
+
     // (000) top right corner
     let mut world = HittableList::from_vec(vec![
-        // light
-        Arc::new(Quad::new(
-            Vec3::new(343.0, 554.0, 332.0),
-            Vec3::new(-130.0, 0.0, 0.0),
-            Vec3::new(0.0, 0.0, -105.0),
-            light,
-            Shape::Square,
-        )),
+        light_quad.clone(),
         // left
         Arc::new(Quad::new(
             Vec3::new(555.0, 0.0, 0.0),
@@ -86,8 +89,10 @@ pub fn cornell_box() {
         Vec3::new(165., 330., 165.),
         white.clone(),
     );
-    let box1 = Arc::new(RotateY::new(box1, 15.0));
-    let box1 = Arc::new(Translate::new(box1, Vec3::new(256., 0.0, 295.)));
+    let box1 = Arc::new(Transform::new(
+        box1,
+        Mat4::rotation_y(15.0).translate(Vec3::new(256., 0.0, 295.)),
+    ));
     world.add(box1);
 
     let box2 = create_box(
@@ -95,13 +100,17 @@ pub fn cornell_box() {
         Vec3::new(165., 165., 165.),
         white.clone(),
     );
-    let box2 = Arc::new(RotateY::new(box2, -18.0));
-    let box2 = Arc::new(Translate::new(box2, Vec3::new(130., 0.0, 65.)));
+    let box2 = Arc::new(Transform::new(
+        box2,
+        Mat4::rotation_y(-18.0).translate(Vec3::new(130., 0.0, 65.)),
+    ));
     world.add(box2);
 
-    let world = BvhNode::from(world);
+    let world = Bvh::from(world);
 
-    if let Err(e) = camera.render(world) {
-        eprintln!("Failed while rendering with error: {e}")
+    Scene {
+        camera,
+        world: Arc::new(world),
+        lights: HittableList::from_vec(vec![light_quad]),
     }
 }
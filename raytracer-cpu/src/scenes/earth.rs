@@ -1,16 +1,12 @@
 use std::sync::Arc;
 
 use crate::{
-    camera::{Camera, CameraConfig},
-    hittables::HittableList,
-    material::Lambertian,
-    sphere::Sphere,
-    texture::ImageTexture,
-    vector::Vec3,
+    camera::CameraConfig, hittables::HittableList, material::Lambertian, scenes::Scene,
+    sphere::Sphere, texture::ImageTexture, vector::Vec3,
 };
 
-pub fn earth() {
-    let camera = Camera::new(CameraConfig {
+pub fn earth() -> Scene {
+    let camera = CameraConfig {
         aspect_ratio: 16.0 / 9.0,
         image_width: 800,
         samples: 100,
@@ -18,7 +14,7 @@ pub fn earth() {
         vfov: 20.0,
         look_from: Vec3::new(0.0, 0.0, 12.0),
         ..CameraConfig::default()
-    });
+    };
 
     let earth_texture = ImageTexture::new("earthmap.jpg");
     let earth_surface = Lambertian::new(Arc::new(earth_texture));
@@ -28,7 +24,9 @@ pub fn earth() {
         Arc::new(earth_surface),
     ))]);
 
-    if let Err(e) = camera.render(globe) {
-        eprintln!("Failed while rendering with error: {e}")
+    Scene {
+        camera,
+        world: Arc::new(globe),
+        lights: HittableList::new(),
     }
 }
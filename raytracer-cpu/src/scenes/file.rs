@@ -0,0 +1,1377 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    ops::{Add, Mul, Range},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    animation::{self, AnimatedTransform, Keyframes, TransformOp},
+    bvh::Bvh,
+    camera::CameraConfig,
+    environment::EnvironmentMap,
+    filter::{Filter as PixelFilter, FilterKind},
+    hittables::{ArcHittable, HittableList, Transform},
+    image::Image,
+    material::{
+        ArcMaterial, Conductor, Dielectric, DiffuseLight, HenyeyGreenstein, Isotropic, Lambertian,
+        Metal, Principled,
+    },
+    matrix::Mat4,
+    normal_map::NormalMap,
+    obj,
+    quad::{create_box, Quad, Shape},
+    sampler::SamplerKind,
+    scenes::Scene,
+    sky::Sky,
+    sphere::Sphere,
+    texture::{
+        ArcTexture, Channel, Checker, FbmTexture, Filter, GridTexture, ImageTexture, NoiseTexture,
+        SolidColor, UvTransform, Wrap,
+    },
+    vector::Vec3,
+    volumes::{ConstantMedium, HeterogeneousMedium},
+};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(String, io::Error),
+    Parse(String, toml::de::Error),
+    /// Scene is syntactically valid but describes something we cannot build
+    Invalid {
+        path: String,
+        line: usize,
+        field: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "could not read '{path}': {e}"),
+            Self::Parse(path, e) => write!(f, "in '{path}': {e}"),
+            Self::Invalid {
+                path,
+                line,
+                field,
+                message,
+            } => write!(f, "in '{path}', line {line}, field `{field}`: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+type Rgb = [f64; 3];
+
+/// Value that is either constant or keyframed, e.g. `vfov = 40.0` or
+/// `vfov = [{ time = 0.0, value = 40.0 }, { time = 2.0, value = 20.0 }]`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Animated<T> {
+    Constant(T),
+    Keyframes(Vec<KeyDesc<T>>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyDesc<T> {
+    time: f64,
+    value: T,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    camera: Option<Spanned<CameraDesc>>,
+    environment: Option<Spanned<EnvironmentDesc>>,
+    sky: Option<Spanned<SkyDesc>>,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    aspect_ratio: Option<f64>,
+    image_width: Option<i32>,
+    samples: Option<i32>,
+    max_depth: Option<i32>,
+    rr_min_depth: Option<i32>,
+    vfov: Option<Animated<f64>>,
+    look_from: Option<Animated<Rgb>>,
+    look_at: Option<Animated<Rgb>>,
+    up_direction: Option<Animated<Rgb>>,
+    defocus_angle: Option<Animated<f64>>,
+    focus_dist: Option<Animated<f64>>,
+    background: Option<Animated<Rgb>>,
+    shutter_open: Option<f64>,
+    shutter_close: Option<f64>,
+    fps: Option<f64>,
+    sampler: Option<SamplerDesc>,
+    filter: Option<PixelFilterDesc>,
+    filter_radius: Option<f64>,
+    adaptive_threshold: Option<f64>,
+    min_samples: Option<i32>,
+    /// In seconds
+    time_limit: Option<f64>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum PixelFilterDesc {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+    BlackmanHarris,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum SamplerDesc {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+/// Equirectangular image lighting the scene, e.g. `file = "studio.hdr"`,
+/// looked up like image textures. Replaces the background of the camera.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDesc {
+    file: String,
+    intensity: Option<Animated<f64>>,
+    /// Around the vertical axis, in degrees
+    rotation: Option<Animated<f64>>,
+}
+
+/// Procedural daylight with a sun, angles in degrees. The azimuth goes from
+/// -z towards +x. Replaces the background of the camera.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkyDesc {
+    sun_elevation: Animated<f64>,
+    sun_azimuth: Option<Animated<f64>>,
+    /// Haziness, from 2 (clear) to 10
+    turbidity: Option<Animated<f64>>,
+    intensity: Option<Animated<f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: Rgb,
+    },
+    Checker {
+        scale: f64,
+        even: Rgb,
+        odd: Rgb,
+    },
+    Image {
+        file: String,
+        /// Use the values stored in the file instead of colors, for normal maps
+        #[serde(default)]
+        raw: bool,
+        /// Nearest texel and clamped coordinates unless asked otherwise
+        #[serde(default)]
+        filter: FilterDesc,
+        #[serde(default)]
+        wrap: WrapDesc,
+        /// Texture coordinates are scaled, rotated (degrees) and then offset
+        scale: Option<[f64; 2]>,
+        offset: Option<[f64; 2]>,
+        rotation: Option<f64>,
+    },
+    /// Marble-like noise, `seed` picks the pattern
+    Noise {
+        scale: f64,
+        seed: Option<u64>,
+    },
+    /// Fractal noise in [0, 1] with `scale` the frequency of the first of
+    /// `octaves` layers, for the density of media
+    Fbm {
+        scale: f64,
+        octaves: Option<u32>,
+        seed: Option<u64>,
+    },
+    /// Voxel grid over the box with corners `a` and `b`, `values` are listed
+    /// with x varying fastest, then y, then z
+    Grid {
+        size: [usize; 3],
+        values: Vec<f64>,
+        a: Rgb,
+        b: Rgb,
+    },
+}
+
+/// Principled material, each parameter is either a value or a texture, see
+/// [`Principled`] for their meaning and defaults
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrincipledDesc {
+    base_color: Option<ParamDesc<Rgb>>,
+    metallic: Option<ParamDesc<f64>>,
+    roughness: Option<ParamDesc<f64>>,
+    specular: Option<ParamDesc<f64>>,
+    specular_tint: Option<ParamDesc<f64>>,
+    sheen: Option<ParamDesc<f64>>,
+    sheen_tint: Option<ParamDesc<f64>>,
+    clearcoat: Option<ParamDesc<f64>>,
+    clearcoat_roughness: Option<ParamDesc<f64>>,
+    transmission: Option<ParamDesc<f64>>,
+    emission: Option<ParamDesc<Rgb>>,
+    ior: Option<Animated<f64>>,
+    normal_map: Option<NormalMapDesc>,
+    bump: Option<BumpDesc>,
+}
+
+/// Parameter of a principled material: a value, a texture or a single
+/// channel of one, e.g. `roughness = 0.4`, `roughness = "rough"` or
+/// `roughness = { texture = "metallic_roughness", channel = "g" }`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ParamDesc<T> {
+    Texture(String),
+    Channel {
+        texture: String,
+        channel: ChannelDesc,
+    },
+    Value(Animated<T>),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ChannelDesc {
+    R,
+    G,
+    B,
+}
+
+/// Tangent space normal map, e.g. `normal_map = { texture = "bricks_normal" }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NormalMapDesc {
+    texture: String,
+    #[serde(default = "one")]
+    strength: f64,
+}
+
+/// Height field, e.g. `bump = { texture = "noise", scale = 0.05 }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BumpDesc {
+    texture: String,
+    #[serde(default = "one")]
+    scale: f64,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum FilterDesc {
+    #[default]
+    Nearest,
+    Bilinear,
+    Trilinear,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum WrapDesc {
+    Repeat,
+    Mirror,
+    #[default]
+    Clamp,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        color: Option<Animated<Rgb>>,
+        texture: Option<String>,
+        normal_map: Option<NormalMapDesc>,
+        bump: Option<BumpDesc>,
+    },
+    Metal {
+        color: Animated<Rgb>,
+        fuzz: Option<Animated<f64>>,
+        normal_map: Option<NormalMapDesc>,
+        bump: Option<BumpDesc>,
+    },
+    /// Metal with a GGX microfacet distribution
+    Conductor {
+        color: Animated<Rgb>,
+        roughness: Option<Animated<f64>>,
+        normal_map: Option<NormalMapDesc>,
+        bump: Option<BumpDesc>,
+    },
+    Dielectric {
+        refraction_index: Animated<f64>,
+        roughness: Option<Animated<f64>>,
+        normal_map: Option<NormalMapDesc>,
+        bump: Option<BumpDesc>,
+    },
+    /// Layered material after glTF's metallic-roughness model
+    Principled(Box<PrincipledDesc>),
+    DiffuseLight {
+        color: Option<Animated<Rgb>>,
+        texture: Option<String>,
+    },
+    Isotropic {
+        color: Option<Animated<Rgb>>,
+        texture: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum ShapeDesc {
+    #[default]
+    Square,
+    Ellipsis,
+    Triangle,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDesc {
+    Translate(Animated<Rgb>),
+    /// Rotations are in degrees
+    RotateX(Animated<f64>),
+    RotateY(Animated<f64>),
+    RotateZ(Animated<f64>),
+    Scale(Animated<ScaleDesc>),
+    /// Row major 4x4 affine matrix
+    Matrix([[f64; 4]; 4]),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    Axes(Rgb),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: Rgb,
+        /// Center at the end of the exposure, for motion blur
+        center_end: Option<Rgb>,
+        radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    Quad {
+        origin: Rgb,
+        u: Rgb,
+        v: Rgb,
+        material: String,
+        #[serde(default)]
+        shape: ShapeDesc,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    Box {
+        a: Rgb,
+        b: Rgb,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    /// Triangle mesh loaded from an OBJ file, relative to the scene file.
+    /// `material` overrides the materials of its MTL files.
+    Mesh {
+        file: String,
+        material: Option<String>,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    ConstantMedium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+        color: Option<Animated<Rgb>>,
+        texture: Option<String>,
+        /// Henyey-Greenstein `g`, isotropic scattering when missing
+        anisotropy: Option<f64>,
+    },
+    /// Medium with `density` scaled by the grey value of `density_texture`
+    HeterogeneousMedium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+        density_texture: String,
+        color: Option<Animated<Rgb>>,
+        texture: Option<String>,
+        anisotropy: Option<f64>,
+    },
+}
+
+impl ObjectDesc {
+    fn material(&self) -> Option<&str> {
+        match self {
+            Self::Sphere { material, .. }
+            | Self::Quad { material, .. }
+            | Self::Box { material, .. } => Some(material),
+            Self::Mesh { material, .. } => material.as_deref(),
+            Self::ConstantMedium { .. } | Self::HeterogeneousMedium { .. } => None,
+        }
+    }
+}
+
+fn vec3(v: Rgb) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn grey(v: f64) -> Vec3 {
+    Vec3::new(v, v, v)
+}
+
+fn scale(desc: ScaleDesc) -> Vec3 {
+    match desc {
+        ScaleDesc::Uniform(factor) => Vec3::new(factor, factor, factor),
+        ScaleDesc::Axes(factors) => vec3(factors),
+    }
+}
+
+fn identity<T>(value: T) -> T {
+    value
+}
+
+/// Load a scene description from a TOML file
+pub fn load(path: &str) -> Result<Scene, SceneError> {
+    load_at(path, 0.0)
+}
+
+/// Load a scene description with its keyframed values taken at `time`
+pub fn load_at(path: &str, time: f64) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_string(), e))?;
+    from_str_at(&source, path, time)
+}
+
+/// Parse a scene description, `path` is used in error messages and to find
+/// the files it refers to
+pub fn from_str(source: &str, path: &str) -> Result<Scene, SceneError> {
+    from_str_at(source, path, 0.0)
+}
+
+/// Same as [`from_str`], with the keyframed values taken at `time`
+pub fn from_str_at(source: &str, path: &str, time: f64) -> Result<Scene, SceneError> {
+    let desc: SceneDesc =
+        toml::from_str(source).map_err(|e| SceneError::Parse(path.to_string(), e))?;
+
+    SceneBuilder {
+        source,
+        path,
+        time,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        light_materials: HashSet::new(),
+    }
+    .build(desc)
+}
+
+struct SceneBuilder<'a> {
+    source: &'a str,
+    path: &'a str,
+    /// Time keyframed values are taken at, objects are animated over the
+    /// whole timeline for motion blur
+    time: f64,
+    textures: HashMap<String, ArcTexture>,
+    materials: HashMap<String, ArcMaterial>,
+    /// Names of the emissive materials, objects using them are sampled as lights
+    light_materials: HashSet<String>,
+}
+
+impl SceneBuilder<'_> {
+    fn error(&self, span: Range<usize>, field: &str, message: String) -> SceneError {
+        let line = self.source[..span.start].matches('\n').count() + 1;
+        SceneError::Invalid {
+            path: self.path.to_string(),
+            line,
+            field: field.to_string(),
+            message,
+        }
+    }
+
+    /// Path of a file the scene refers to, relative to the scene file
+    fn relative_path(&self, file: &str) -> String {
+        let dir = Path::new(self.path).parent().unwrap_or(Path::new(""));
+        dir.join(file).to_string_lossy().into_owned()
+    }
+
+    fn build(mut self, desc: SceneDesc) -> Result<Scene, SceneError> {
+        let mut camera = match desc.camera {
+            Some(camera) => {
+                let span = camera.span();
+                self.camera(camera.into_inner(), span)?
+            }
+            None => CameraConfig::default(),
+        };
+        camera.environment = match (desc.environment, desc.sky) {
+            (Some(environment), None) => {
+                let span = environment.span();
+                Some(Arc::new(self.environment(environment.into_inner(), span)?))
+            }
+            (None, Some(sky)) => {
+                let span = sky.span();
+                Some(Arc::new(self.sky(sky.into_inner(), span)?))
+            }
+            (Some(_), Some(sky)) => {
+                return Err(self.error(
+                    sky.span(),
+                    "sky",
+                    "`environment` and `sky` are mutually exclusive".to_string(),
+                ))
+            }
+            (None, None) => None,
+        };
+
+        for (name, texture) in desc.textures {
+            let field = format!("textures.{name}");
+            let span = texture.span();
+            let texture = self.texture(texture.into_inner(), span, &field)?;
+            self.textures.insert(name, texture);
+        }
+
+        for (name, material) in desc.materials {
+            let field = format!("materials.{name}");
+            let span = material.span();
+            let material = material.into_inner();
+            if matches!(material, MaterialDesc::DiffuseLight { .. })
+                || matches!(&material, MaterialDesc::Principled(desc) if desc.emission.is_some())
+            {
+                self.light_materials.insert(name.clone());
+            }
+
+            let material = self.material(material, span, &field)?;
+            self.materials.insert(name, material);
+        }
+
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        for (i, object) in desc.objects.into_iter().enumerate() {
+            let field = format!("objects[{i}]");
+            let span = object.span();
+            let object = object.into_inner();
+            let is_light = object
+                .material()
+                .is_some_and(|name| self.light_materials.contains(name));
+
+            let (object, emitters) = self.object_with_emitters(object, span, &field)?;
+            if is_light {
+                lights.add(object.clone());
+            } else if let Some(emitters) = emitters {
+                lights.add(emitters);
+            }
+            world.add(object);
+        }
+
+        if world.objects.is_empty() {
+            return Err(self.error(0..0, "objects", "scene has no objects".to_string()));
+        }
+
+        Ok(Scene {
+            camera,
+            world: Arc::new(Bvh::from(world)),
+            lights,
+        })
+    }
+
+    /// Keyframes of an animated value, `convert` maps the file values to ours
+    fn keyframes<T, U>(
+        &self,
+        value: Animated<T>,
+        convert: impl Fn(T) -> U,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Keyframes<U>, SceneError>
+    where
+        U: Copy + Add<Output = U>,
+        f64: Mul<U, Output = U>,
+    {
+        match value {
+            Animated::Constant(value) => Ok(Keyframes::constant(convert(value))),
+            Animated::Keyframes(keys) if keys.is_empty() => {
+                Err(self.error(span, field, "expected at least one keyframe".to_string()))
+            }
+            Animated::Keyframes(keys) => Ok(Keyframes::new(
+                keys.into_iter()
+                    .map(|key| (key.time, convert(key.value)))
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Value of an animated field at the time of the scene, or `default`
+    fn value_at<T, U>(
+        &self,
+        value: Option<Animated<T>>,
+        default: U,
+        convert: impl Fn(T) -> U,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<U, SceneError>
+    where
+        U: Copy + Add<Output = U>,
+        f64: Mul<U, Output = U>,
+    {
+        match value {
+            Some(value) => Ok(self.keyframes(value, convert, span, field)?.at(self.time)),
+            None => Ok(default),
+        }
+    }
+
+    fn environment(
+        &self,
+        desc: EnvironmentDesc,
+        span: Range<usize>,
+    ) -> Result<EnvironmentMap, SceneError> {
+        let intensity = self.value_at(
+            desc.intensity,
+            1.0,
+            identity,
+            span.clone(),
+            "environment.intensity",
+        )?;
+        let rotation = self.value_at(
+            desc.rotation,
+            0.0,
+            identity,
+            span.clone(),
+            "environment.rotation",
+        )?;
+        let image = Image::try_open(&self.relative_path(&desc.file))
+            .map_err(|message| self.error(span, "environment.file", message))?;
+        Ok(EnvironmentMap::new(image)
+            .with_intensity(intensity)
+            .with_rotation(rotation))
+    }
+
+    fn sky(&self, desc: SkyDesc, span: Range<usize>) -> Result<Sky, SceneError> {
+        let s = || span.clone();
+        let turbidity = self.value_at(desc.turbidity, 3.0, identity, s(), "sky.turbidity")?;
+        if turbidity < 1.0 {
+            return Err(self.error(
+                span,
+                "sky.turbidity",
+                "expected a turbidity of at least 1".to_string(),
+            ));
+        }
+
+        let elevation = self.value_at(
+            Some(desc.sun_elevation),
+            0.0,
+            identity,
+            s(),
+            "sky.sun_elevation",
+        )?;
+        let azimuth = self.value_at(desc.sun_azimuth, 0.0, identity, s(), "sky.sun_azimuth")?;
+        let intensity = self.value_at(desc.intensity, 1.0, identity, s(), "sky.intensity")?;
+        Ok(Sky::new(elevation, azimuth, turbidity).with_intensity(intensity))
+    }
+
+    fn camera(&self, desc: CameraDesc, span: Range<usize>) -> Result<CameraConfig, SceneError> {
+        let default = CameraConfig::default();
+        let s = || span.clone();
+        let fps = desc.fps.unwrap_or(default.fps);
+        if fps <= 0.0 {
+            return Err(self.error(s(), "camera.fps", "fps must be positive".to_string()));
+        }
+        let filter = match desc.filter {
+            None => default.filter,
+            Some(kind) => PixelFilter::new(match kind {
+                PixelFilterDesc::Box => FilterKind::Box,
+                PixelFilterDesc::Tent => FilterKind::Tent,
+                PixelFilterDesc::Gaussian => FilterKind::Gaussian,
+                PixelFilterDesc::Mitchell => FilterKind::Mitchell,
+                PixelFilterDesc::Lanczos => FilterKind::Lanczos,
+                PixelFilterDesc::BlackmanHarris => FilterKind::BlackmanHarris,
+            }),
+        };
+        let filter = match desc.filter_radius {
+            Some(radius) if radius > 0.0 && radius.is_finite() => filter.with_radius(radius),
+            Some(_) => {
+                return Err(self.error(
+                    s(),
+                    "camera.filter_radius",
+                    "filter radius must be positive".to_string(),
+                ))
+            }
+            None => filter,
+        };
+        if desc
+            .time_limit
+            .is_some_and(|limit| !(limit > 0.0 && limit.is_finite()))
+        {
+            return Err(self.error(
+                s(),
+                "camera.time_limit",
+                "time limit must be a positive number of seconds".to_string(),
+            ));
+        }
+
+        Ok(CameraConfig {
+            aspect_ratio: desc.aspect_ratio.unwrap_or(default.aspect_ratio),
+            image_width: desc.image_width.unwrap_or(default.image_width),
+            samples: desc.samples.unwrap_or(default.samples),
+            max_depth: desc.max_depth.unwrap_or(default.max_depth),
+            rr_min_depth: desc.rr_min_depth.unwrap_or(default.rr_min_depth),
+            vfov: self.value_at(desc.vfov, default.vfov, identity, s(), "camera.vfov")?,
+            look_from: self.value_at(
+                desc.look_from,
+                default.look_from,
+                vec3,
+                s(),
+                "camera.look_from",
+            )?,
+            look_at: self.value_at(desc.look_at, default.look_at, vec3, s(), "camera.look_at")?,
+            up_direction: self.value_at(
+                desc.up_direction,
+                default.up_direction,
+                vec3,
+                s(),
+                "camera.up_direction",
+            )?,
+            defocus_angle: self.value_at(
+                desc.defocus_angle,
+                default.defocus_angle,
+                identity,
+                s(),
+                "camera.defocus_angle",
+            )?,
+            focus_dist: self.value_at(
+                desc.focus_dist,
+                default.focus_dist,
+                identity,
+                s(),
+                "camera.focus_dist",
+            )?,
+            background: self.value_at(
+                desc.background,
+                default.background,
+                vec3,
+                s(),
+                "camera.background",
+            )?,
+            time: self.time,
+            shutter_open: desc.shutter_open.unwrap_or(default.shutter_open),
+            shutter_close: desc.shutter_close.unwrap_or(default.shutter_close),
+            fps,
+            sampler: match desc.sampler {
+                None => default.sampler,
+                Some(SamplerDesc::Independent) => SamplerKind::Independent,
+                Some(SamplerDesc::Stratified) => SamplerKind::Stratified,
+                Some(SamplerDesc::Halton) => SamplerKind::Halton,
+                Some(SamplerDesc::Sobol) => SamplerKind::Sobol,
+                Some(SamplerDesc::BlueNoise) => SamplerKind::BlueNoise,
+            },
+            filter,
+            adaptive_threshold: desc.adaptive_threshold.or(default.adaptive_threshold),
+            min_samples: desc.min_samples.unwrap_or(default.min_samples),
+            time_limit: desc
+                .time_limit
+                .map(Duration::from_secs_f64)
+                .or(default.time_limit),
+            ..default
+        })
+    }
+
+    fn texture(
+        &self,
+        desc: TextureDesc,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<ArcTexture, SceneError> {
+        Ok(match desc {
+            TextureDesc::Solid { color } => Arc::new(SolidColor::new(vec3(color))),
+            TextureDesc::Checker { scale, even, odd } => {
+                Arc::new(Checker::from_colors(scale, vec3(even), vec3(odd)))
+            }
+            TextureDesc::Image {
+                file,
+                raw,
+                filter,
+                wrap,
+                scale,
+                offset,
+                rotation,
+            } => {
+                let filter = match filter {
+                    FilterDesc::Nearest => Filter::Nearest,
+                    FilterDesc::Bilinear => Filter::Bilinear,
+                    FilterDesc::Trilinear => Filter::Trilinear,
+                };
+                let wrap = match wrap {
+                    WrapDesc::Repeat => Wrap::Repeat,
+                    WrapDesc::Mirror => Wrap::Mirror,
+                    WrapDesc::Clamp => Wrap::Clamp,
+                };
+                let default = UvTransform::default();
+                let transform = UvTransform {
+                    scale: scale.map_or(default.scale, |[u, v]| (u, v)),
+                    offset: offset.map_or(default.offset, |[u, v]| (u, v)),
+                    rotation: rotation.unwrap_or(default.rotation),
+                };
+
+                let image = Image::try_open(&self.relative_path(&file))
+                    .map_err(|message| self.error(span, &format!("{field}.file"), message))?;
+                let texture = ImageTexture::from_image(image);
+                let texture = if raw { texture.raw() } else { texture };
+                Arc::new(
+                    texture
+                        .with_filter(filter)
+                        .with_wrap(wrap)
+                        .with_transform(transform),
+                )
+            }
+            TextureDesc::Noise { scale, seed } => {
+                Arc::new(NoiseTexture::new(scale, seed.unwrap_or_default()))
+            }
+            TextureDesc::Fbm {
+                scale,
+                octaves,
+                seed,
+            } => Arc::new(FbmTexture::new(
+                scale,
+                octaves.unwrap_or(5),
+                seed.unwrap_or_default(),
+            )),
+            TextureDesc::Grid { size, values, a, b } => {
+                let expected: usize = size.iter().product();
+                if expected == 0 || values.len() != expected {
+                    return Err(self.error(
+                        span,
+                        &format!("{field}.values"),
+                        format!(
+                            "expected {expected} values for a {}x{}x{} grid, got {}",
+                            size[0],
+                            size[1],
+                            size[2],
+                            values.len()
+                        ),
+                    ));
+                }
+                Arc::new(GridTexture::new(size, values, vec3(a), vec3(b)))
+            }
+        })
+    }
+
+    /// Resolve a material property that can be given either as a plain
+    /// `color` or as the name of a texture
+    fn color_or_texture(
+        &self,
+        color: Option<Animated<Rgb>>,
+        texture: Option<String>,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<ArcTexture, SceneError> {
+        match (color, texture) {
+            (Some(color), None) => {
+                let field = format!("{field}.color");
+                let color = self.keyframes(color, vec3, span, &field)?.at(self.time);
+                Ok(Arc::new(SolidColor::new(color)))
+            }
+            (None, Some(name)) => self.lookup_texture(&name, span, &format!("{field}.texture")),
+            (Some(_), Some(_)) => Err(self.error(
+                span,
+                field,
+                "`color` and `texture` are mutually exclusive".to_string(),
+            )),
+            (None, None) => Err(self.error(
+                span,
+                field,
+                "expected either `color` or `texture`".to_string(),
+            )),
+        }
+    }
+
+    /// Texture of a principled material parameter
+    fn param<T>(
+        &self,
+        desc: ParamDesc<T>,
+        convert: impl Fn(T) -> Vec3,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<ArcTexture, SceneError> {
+        match desc {
+            ParamDesc::Texture(name) => self.lookup_texture(&name, span, field),
+            ParamDesc::Channel { texture, channel } => {
+                let texture = self.lookup_texture(&texture, span, field)?;
+                Ok(Arc::new(Channel::new(texture, channel as usize)))
+            }
+            ParamDesc::Value(value) => {
+                let value = self.keyframes(value, convert, span, field)?.at(self.time);
+                Ok(Arc::new(SolidColor::new(value)))
+            }
+        }
+    }
+
+    fn material(
+        &self,
+        desc: MaterialDesc,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<ArcMaterial, SceneError> {
+        let material: ArcMaterial = match desc {
+            MaterialDesc::Lambertian {
+                color,
+                texture,
+                normal_map,
+                bump,
+            } => {
+                let normal_map = self.normal_map(normal_map, bump, span.clone(), field)?;
+                let material = Lambertian::new(self.color_or_texture(color, texture, span, field)?);
+                match normal_map {
+                    Some(normal_map) => Arc::new(material.with_normal_map(normal_map)),
+                    None => Arc::new(material),
+                }
+            }
+            MaterialDesc::Metal {
+                color,
+                fuzz,
+                normal_map,
+                bump,
+            } => {
+                let normal_map = self.normal_map(normal_map, bump, span.clone(), field)?;
+                let color_field = format!("{field}.color");
+                let color = self.value_at(
+                    Some(color),
+                    Vec3::default(),
+                    vec3,
+                    span.clone(),
+                    &color_field,
+                )?;
+                let fuzz = self.value_at(fuzz, 0.0, identity, span, &format!("{field}.fuzz"))?;
+                let material = Metal::new(color, fuzz);
+                match normal_map {
+                    Some(normal_map) => Arc::new(material.with_normal_map(normal_map)),
+                    None => Arc::new(material),
+                }
+            }
+            MaterialDesc::Conductor {
+                color,
+                roughness,
+                normal_map,
+                bump,
+            } => {
+                let normal_map = self.normal_map(normal_map, bump, span.clone(), field)?;
+                let color_field = format!("{field}.color");
+                let color = self.value_at(
+                    Some(color),
+                    Vec3::default(),
+                    vec3,
+                    span.clone(),
+                    &color_field,
+                )?;
+                let roughness_field = format!("{field}.roughness");
+                let roughness = self.value_at(roughness, 0.0, identity, span, &roughness_field)?;
+                let material = Conductor::new(color, roughness);
+                match normal_map {
+                    Some(normal_map) => Arc::new(material.with_normal_map(normal_map)),
+                    None => Arc::new(material),
+                }
+            }
+            MaterialDesc::Dielectric {
+                refraction_index,
+                roughness,
+                normal_map,
+                bump,
+            } => {
+                let normal_map = self.normal_map(normal_map, bump, span.clone(), field)?;
+                let roughness_field = format!("{field}.roughness");
+                let roughness =
+                    self.value_at(roughness, 0.0, identity, span.clone(), &roughness_field)?;
+                let field = format!("{field}.refraction_index");
+                let refraction_index =
+                    self.value_at(Some(refraction_index), 1.0, identity, span, &field)?;
+                let material = Dielectric::new(refraction_index).with_roughness(roughness);
+                match normal_map {
+                    Some(normal_map) => Arc::new(material.with_normal_map(normal_map)),
+                    None => Arc::new(material),
+                }
+            }
+            MaterialDesc::Principled(desc) => {
+                let PrincipledDesc {
+                    base_color,
+                    metallic,
+                    roughness,
+                    specular,
+                    specular_tint,
+                    sheen,
+                    sheen_tint,
+                    clearcoat,
+                    clearcoat_roughness,
+                    transmission,
+                    emission,
+                    ior,
+                    normal_map,
+                    bump,
+                } = *desc;
+                let normal_map = self.normal_map(normal_map, bump, span.clone(), field)?;
+                let color = |desc: Option<ParamDesc<Rgb>>, name: &str| {
+                    desc.map(|desc| {
+                        self.param(desc, vec3, span.clone(), &format!("{field}.{name}"))
+                    })
+                    .transpose()
+                };
+                let scalar = |desc: Option<ParamDesc<f64>>, name: &str| {
+                    desc.map(|desc| {
+                        self.param(desc, grey, span.clone(), &format!("{field}.{name}"))
+                    })
+                    .transpose()
+                };
+
+                let base_color = color(base_color, "base_color")?
+                    .unwrap_or_else(|| Arc::new(SolidColor::new(Vec3::new(1.0, 1.0, 1.0))));
+                let mut material = Principled::new(base_color);
+                if let Some(metallic) = scalar(metallic, "metallic")? {
+                    material = material.with_metallic(metallic);
+                }
+                if let Some(roughness) = scalar(roughness, "roughness")? {
+                    material = material.with_roughness(roughness);
+                }
+                if let Some(specular) = scalar(specular, "specular")? {
+                    material = material.with_specular(specular);
+                }
+                if let Some(specular_tint) = scalar(specular_tint, "specular_tint")? {
+                    material = material.with_specular_tint(specular_tint);
+                }
+                if let Some(sheen) = scalar(sheen, "sheen")? {
+                    material = material.with_sheen(sheen);
+                }
+                if let Some(sheen_tint) = scalar(sheen_tint, "sheen_tint")? {
+                    material = material.with_sheen_tint(sheen_tint);
+                }
+                if let Some(clearcoat) = scalar(clearcoat, "clearcoat")? {
+                    material = material.with_clearcoat(clearcoat);
+                }
+                if let Some(roughness) = scalar(clearcoat_roughness, "clearcoat_roughness")? {
+                    material = material.with_clearcoat_roughness(roughness);
+                }
+                if let Some(transmission) = scalar(transmission, "transmission")? {
+                    material = material.with_transmission(transmission);
+                }
+                if let Some(emission) = color(emission, "emission")? {
+                    material = material.with_emission(emission);
+                }
+                let ior_field = format!("{field}.ior");
+                let material =
+                    material.with_ior(self.value_at(ior, 1.5, identity, span, &ior_field)?);
+
+                match normal_map {
+                    Some(normal_map) => Arc::new(material.with_normal_map(normal_map)),
+                    None => Arc::new(material),
+                }
+            }
+            MaterialDesc::DiffuseLight { color, texture } => Arc::new(DiffuseLight::new(
+                self.color_or_texture(color, texture, span, field)?,
+            )),
+            MaterialDesc::Isotropic { color, texture } => Arc::new(Isotropic::new(
+                self.color_or_texture(color, texture, span, field)?,
+            )),
+        };
+
+        Ok(material)
+    }
+
+    fn lookup_texture(
+        &self,
+        name: &str,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<ArcTexture, SceneError> {
+        self.textures
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.error(span, field, format!("unknown texture `{name}`")))
+    }
+
+    /// Phase function of a medium, Henyey-Greenstein with `anisotropy` as `g`
+    /// or isotropic when it is missing
+    fn phase_function(
+        &self,
+        texture: ArcTexture,
+        anisotropy: Option<f64>,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<ArcMaterial, SceneError> {
+        match anisotropy {
+            None => Ok(Arc::new(Isotropic::new(texture))),
+            Some(g) if g > -1.0 && g < 1.0 => Ok(Arc::new(HenyeyGreenstein::new(texture, g))),
+            Some(_) => Err(self.error(
+                span,
+                &format!("{field}.anisotropy"),
+                "anisotropy must be between -1 and 1".to_string(),
+            )),
+        }
+    }
+
+    /// Surface detail of a material, given either as a normal map or as a
+    /// bump map
+    fn normal_map(
+        &self,
+        normal_map: Option<NormalMapDesc>,
+        bump: Option<BumpDesc>,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Option<NormalMap>, SceneError> {
+        match (normal_map, bump) {
+            (Some(desc), None) => Ok(Some(NormalMap::Tangent {
+                texture: self.lookup_texture(
+                    &desc.texture,
+                    span,
+                    &format!("{field}.normal_map.texture"),
+                )?,
+                strength: desc.strength,
+            })),
+            (None, Some(desc)) => Ok(Some(NormalMap::Bump {
+                height: self.lookup_texture(
+                    &desc.texture,
+                    span,
+                    &format!("{field}.bump.texture"),
+                )?,
+                scale: desc.scale,
+            })),
+            (None, None) => Ok(None),
+            (Some(_), Some(_)) => Err(self.error(
+                span,
+                field,
+                "`normal_map` and `bump` are mutually exclusive".to_string(),
+            )),
+        }
+    }
+
+    fn lookup_material(
+        &self,
+        name: &str,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<ArcMaterial, SceneError> {
+        self.materials.get(name).cloned().ok_or_else(|| {
+            self.error(
+                span,
+                &format!("{field}.material"),
+                format!("unknown material `{name}`"),
+            )
+        })
+    }
+
+    fn object(
+        &self,
+        desc: ObjectDesc,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<ArcHittable, SceneError> {
+        Ok(self.object_with_emitters(desc, span, field)?.0)
+    }
+
+    /// Object described by `desc`, and its parts glowing through materials
+    /// of their own rather than one of the scene, to sample as lights: the
+    /// faces of a mesh with an emissive MTL material
+    fn object_with_emitters(
+        &self,
+        desc: ObjectDesc,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<(ArcHittable, Option<ArcHittable>), SceneError> {
+        let transform_span = span.clone();
+        let mut emitters: Option<ArcHittable> = None;
+        let (object, transform): (ArcHittable, _) = match desc {
+            ObjectDesc::Sphere {
+                center,
+                center_end,
+                radius,
+                material,
+                transform,
+            } => {
+                let material = self.lookup_material(&material, span, field)?;
+                let sphere = match center_end {
+                    Some(end) => Sphere::new_in_motion(vec3(center), vec3(end), radius, material),
+                    None => Sphere::new(vec3(center), radius, material),
+                };
+                (Arc::new(sphere), transform)
+            }
+            ObjectDesc::Quad {
+                origin,
+                u,
+                v,
+                material,
+                shape,
+                transform,
+            } => {
+                let material = self.lookup_material(&material, span, field)?;
+                let shape = match shape {
+                    ShapeDesc::Square => Shape::Square,
+                    ShapeDesc::Ellipsis => Shape::Ellipsis,
+                    ShapeDesc::Triangle => Shape::Triangle,
+                };
+                let quad = Quad::new(vec3(origin), vec3(u), vec3(v), material, shape);
+                (Arc::new(quad), transform)
+            }
+            ObjectDesc::Box {
+                a,
+                b,
+                material,
+                transform,
+            } => {
+                let material = self.lookup_material(&material, span, field)?;
+                (create_box(vec3(a), vec3(b), material), transform)
+            }
+            ObjectDesc::Mesh {
+                file,
+                material,
+                transform,
+            } => {
+                let default: ArcMaterial = Arc::new(Lambertian::default());
+                let mesh = obj::load(&self.relative_path(&file), default).map_err(|e| {
+                    self.error(span.clone(), &format!("{field}.file"), e.to_string())
+                })?;
+
+                let mesh = match material {
+                    Some(name) => mesh.with_material(self.lookup_material(&name, span, field)?),
+                    None => {
+                        emitters = mesh.emissive().map(|faces| Arc::new(faces) as ArcHittable);
+                        mesh
+                    }
+                };
+                (Arc::new(mesh), transform)
+            }
+            ObjectDesc::ConstantMedium {
+                boundary,
+                density,
+                color,
+                texture,
+                anisotropy,
+            } => {
+                if density <= 0.0 {
+                    return Err(self.error(
+                        span,
+                        &format!("{field}.density"),
+                        "density must be positive".to_string(),
+                    ));
+                }
+                let boundary =
+                    self.object(*boundary, span.clone(), &format!("{field}.boundary"))?;
+                let texture = self.color_or_texture(color, texture, span.clone(), field)?;
+                let phase_function =
+                    self.phase_function(texture.clone(), anisotropy, span, field)?;
+                (
+                    Arc::new(
+                        ConstantMedium::new(boundary, density, texture)
+                            .with_phase_function(phase_function),
+                    ),
+                    Vec::new(),
+                )
+            }
+            ObjectDesc::HeterogeneousMedium {
+                boundary,
+                density,
+                density_texture,
+                color,
+                texture,
+                anisotropy,
+            } => {
+                if density <= 0.0 {
+                    return Err(self.error(
+                        span,
+                        &format!("{field}.density"),
+                        "density must be positive".to_string(),
+                    ));
+                }
+                let boundary =
+                    self.object(*boundary, span.clone(), &format!("{field}.boundary"))?;
+                let density_texture = self.lookup_texture(
+                    &density_texture,
+                    span.clone(),
+                    &format!("{field}.density_texture"),
+                )?;
+                let texture = self.color_or_texture(color, texture, span.clone(), field)?;
+                let phase_function =
+                    self.phase_function(texture.clone(), anisotropy, span, field)?;
+                (
+                    Arc::new(
+                        HeterogeneousMedium::new(boundary, density_texture, density, texture)
+                            .with_phase_function(phase_function),
+                    ),
+                    Vec::new(),
+                )
+            }
+        };
+
+        if transform.is_empty() {
+            return Ok((object, emitters));
+        }
+
+        // Transforms are applied in the order they are listed
+        let field = format!("{field}.transform");
+        let mut ops = Vec::with_capacity(transform.len());
+        for transform in transform {
+            let s = transform_span.clone();
+            ops.push(match transform {
+                TransformDesc::Translate(offset) => {
+                    TransformOp::Translate(self.keyframes(offset, vec3, s, &field)?)
+                }
+                TransformDesc::RotateX(angle) => {
+                    TransformOp::RotateX(self.keyframes(angle, identity, s, &field)?)
+                }
+                TransformDesc::RotateY(angle) => {
+                    TransformOp::RotateY(self.keyframes(angle, identity, s, &field)?)
+                }
+                TransformDesc::RotateZ(angle) => {
+                    TransformOp::RotateZ(self.keyframes(angle, identity, s, &field)?)
+                }
+                TransformDesc::Scale(factors) => {
+                    TransformOp::Scale(self.keyframes(factors, scale, s, &field)?)
+                }
+                TransformDesc::Matrix(m) => TransformOp::Matrix(Mat4::new(m)),
+            });
+        }
+
+        // Objects would vanish wherever the transformation is singular
+        let animated = !ops.iter().all(TransformOp::is_constant);
+        for time in animation::keyframe_times(&ops) {
+            if animation::compose(&ops, time).inverse().is_none() {
+                let message = if animated {
+                    format!("transformation is not invertible at time {time}")
+                } else {
+                    "transformation is not invertible".to_string()
+                };
+                return Err(self.error(transform_span, &field, message));
+            }
+        }
+
+        if animated {
+            let emitters = emitters.map(|emitters| {
+                Arc::new(AnimatedTransform::new(emitters, ops.clone())) as ArcHittable
+            });
+            return Ok((Arc::new(AnimatedTransform::new(object, ops)), emitters));
+        }
+
+        let matrix = animation::compose(&ops, 0.0);
+
+        let emitters =
+            emitters.map(|emitters| Arc::new(Transform::new(emitters, matrix)) as ArcHittable);
+        Ok((Arc::new(Transform::new(object, matrix)), emitters))
+    }
+}
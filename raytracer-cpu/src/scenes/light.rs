@@ -1,17 +1,18 @@
 use std::sync::Arc;
 
 use crate::{
-    camera::{Camera, CameraConfig},
+    camera::CameraConfig,
     color::Color,
     hittables::HittableList,
     material::{DiffuseLight, Lambertian},
     quad::{Quad, Shape},
+    scenes::Scene,
     sphere::Sphere,
     vector::Vec3,
 };
 
-pub fn light() {
-    let camera = Camera::new(CameraConfig {
+pub fn light() -> Scene {
+    let camera = CameraConfig {
         aspect_ratio: 16.0 / 9.0,
         image_width: 800,
         samples: 100,
@@ -21,18 +22,12 @@ pub fn light() {
         look_from: Vec3::new(26.0, 3.0, 6.0),
         look_at: Vec3::new(0.0, 2.0, 0.0),
         ..CameraConfig::default()
-    });
+    };
 
     let red = Arc::new(Lambertian::from_rgb(1.0, 0.2, 0.2));
     let diff_light = Arc::new(DiffuseLight::from_rgb(4.0, 4.0, 4.0));
 
-    let world = HittableList::from_vec(vec![
-        Arc::new(Sphere::with_arc(
-            Vec3::new(0.0, -1000.0, 0.0),
-            1000.0,
-            red.clone(),
-        )),
-        Arc::new(Sphere::with_arc(Vec3::new(0.0, 2.0, 0.0), 2.0, red)),
+    let lights = HittableList::from_vec(vec![
         Arc::new(Sphere::with_arc(
             Vec3::new(0.0, 7.0, 0.0),
             2.0,
@@ -47,7 +42,22 @@ pub fn light() {
         )),
     ]);
 
-    if let Err(e) = camera.render(world) {
-        eprintln!("Failed while rendering with error: {e}")
+    let mut world = HittableList::from_vec(vec![
+        Arc::new(Sphere::with_arc(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            red.clone(),
+        )),
+        Arc::new(Sphere::with_arc(Vec3::new(0.0, 2.0, 0.0), 2.0, red)),
+    ]);
+    lights
+        .objects
+        .iter()
+        .for_each(|light| world.add(light.clone()));
+
+    Scene {
+        camera,
+        world: Arc::new(world),
+        lights,
     }
 }
@@ -1,8 +1,14 @@
+use crate::{
+    camera::CameraConfig,
+    hittables::{ArcHittable, HittableList},
+};
+
 mod bouncing_spheres;
 mod checkered_spheres;
 mod cornell;
 mod cornell_smoke;
 mod earth;
+pub mod file;
 mod light;
 mod perlin;
 mod quads;
@@ -17,3 +23,34 @@ pub use light::light;
 pub use perlin::perlin_spheres;
 pub use quads::quads;
 pub use the_week_after::final_scene as the_week_after;
+
+/// A world ready to be rendered together with the camera looking at it
+pub struct Scene {
+    pub camera: CameraConfig,
+    pub world: ArcHittable,
+    /// Emissive objects of the world to sample explicitly
+    pub lights: HittableList,
+}
+
+/// Builds a scene, whatever is random in it picked from the seed
+pub type SceneBuilder = fn(u64) -> Scene;
+
+/// Builders for the scenes shipped with the crate, keyed by name
+pub const SCENES: [(&str, SceneBuilder); 9] = [
+    ("bouncing_spheres", bouncing_spheres),
+    ("checkered_spheres", |_| checkered_spheres()),
+    ("earth", |_| earth()),
+    ("quads", |_| quads()),
+    ("light", |_| light()),
+    ("perlin", perlin_spheres),
+    ("cornell_box", |_| cornell_box()),
+    ("cornell_smoke", |_| cornell_smoke()),
+    ("the_week_after", the_week_after),
+];
+
+pub fn by_name(name: &str, seed: u64) -> Option<Scene> {
+    SCENES
+        .iter()
+        .find(|(scene_name, _)| *scene_name == name)
+        .map(|(_, build)| build(seed))
+}
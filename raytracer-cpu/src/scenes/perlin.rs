@@ -1,16 +1,12 @@
 use std::sync::Arc;
 
 use crate::{
-    camera::{Camera, CameraConfig},
-    hittables::HittableList,
-    material::Lambertian,
-    sphere::Sphere,
-    texture::NoiseTexture,
-    vector::Vec3,
+    camera::CameraConfig, hittables::HittableList, material::Lambertian, scenes::Scene,
+    sphere::Sphere, texture::NoiseTexture, vector::Vec3,
 };
 
-pub fn perlin_spheres() {
-    let camera = Camera::new(CameraConfig {
+pub fn perlin_spheres(seed: u64) -> Scene {
+    let camera = CameraConfig {
         aspect_ratio: 16.0 / 9.0,
         image_width: 800,
         samples: 100,
@@ -19,9 +15,9 @@ pub fn perlin_spheres() {
         look_from: Vec3::new(13.0, 2.0, 3.0),
         look_at: Vec3::new(0.0, 0.0, 0.0),
         ..CameraConfig::default()
-    });
+    };
 
-    let perlin_texture = NoiseTexture::new(4.0);
+    let perlin_texture = NoiseTexture::new(4.0, seed);
     let material = Arc::new(Lambertian::new(Arc::new(perlin_texture)));
 
     let globe = HittableList::from_vec(vec![
@@ -33,7 +29,9 @@ pub fn perlin_spheres() {
         Arc::new(Sphere::new(Vec3::new(0.0, 2.0, 0.0), 2.0, material)),
     ]);
 
-    if let Err(e) = camera.render(globe) {
-        eprintln!("Failed while rendering with error: {e}")
+    Scene {
+        camera,
+        world: Arc::new(globe),
+        lights: HittableList::new(),
     }
 }
@@ -1,16 +1,17 @@
 use std::sync::Arc;
 
 use crate::{
-    boundind_box::BvhNode,
-    camera::{Camera, CameraConfig},
+    bvh::Bvh,
+    camera::CameraConfig,
     hittables::HittableList,
     material::Lambertian,
     quad::{Quad, Shape},
+    scenes::Scene,
     vector::Vec3,
 };
 
-pub fn quads() {
-    let camera = Camera::new(CameraConfig {
+pub fn quads() -> Scene {
+    let camera = CameraConfig {
         aspect_ratio: 1.0,
         image_width: 800,
         samples: 100,
@@ -18,7 +19,7 @@ pub fn quads() {
         vfov: 80.0,
         look_from: Vec3::new(0.0, 0.0, 9.0),
         ..CameraConfig::default()
-    });
+    };
 
     let left_red = Arc::new(Lambertian::from_rgb(1.0, 0.2, 0.2));
     let back_green = Arc::new(Lambertian::from_rgb(0.2, 1.0, 0.2));
@@ -87,9 +88,11 @@ pub fn quads() {
         )),
     ]);
 
-    let world = BvhNode::from(world);
+    let world = Bvh::from(world);
 
-    if let Err(e) = camera.render(world) {
-        eprintln!("Failed while rendering with error: {e}")
+    Scene {
+        camera,
+        world: Arc::new(world),
+        lights: HittableList::new(),
     }
 }
@@ -1,21 +1,25 @@
 use std::sync::Arc;
 
 use crate::{
-    boundind_box::BvhNode,
-    camera::{Camera, CameraConfig},
+    bvh::Bvh,
+    camera::CameraConfig,
     color::Color,
-    hittables::{HittableList, RotateY, Translate},
+    hittables::{ArcHittable, HittableList, Transform},
     material::{Dielectric, DiffuseLight, Lambertian, Metal},
+    matrix::Mat4,
     quad::{create_box, Quad, Shape},
-    random,
+    random::Generator,
+    scenes::Scene,
     sphere::Sphere,
     texture::{ImageTexture, NoiseTexture},
     vector::Vec3,
     volumes::ConstantMedium,
 };
 
-pub fn final_scene() {
-    let camera = Camera::new(CameraConfig {
+pub fn final_scene(seed: u64) -> Scene {
+    let mut rng = Generator::new(seed);
+
+    let camera = CameraConfig {
         aspect_ratio: 1.0,
         image_width: 800,
         samples: 1000,
@@ -24,8 +28,12 @@ pub fn final_scene() {
         look_from: Vec3::new(478.0, 278.0, -600.0),
         look_at: Vec3::new(278.0, 278.0, 0.0),
         background: Color::BLACK,
+        // The moving spheres bounce over one second, as in the book the
+        // shutter stays open for all of it
+        fps: 1.0,
+        shutter_close: 1.0,
         ..CameraConfig::default()
-    });
+    };
 
     let ground = Arc::new(Lambertian::from_rgb(0.48, 0.83, 0.53));
     let boxes_per_side = 20;
@@ -40,7 +48,7 @@ pub fn final_scene() {
             let z0 = -1000.0 + width * j as f64;
 
             let x1 = x0 + width;
-            let y1 = random::in_interval(1.0, 101.0);
+            let y1 = rng.in_interval(1.0, 101.0);
             let z1 = z0 + width;
 
             boxes_1.add(create_box(
@@ -52,16 +60,17 @@ pub fn final_scene() {
     }
 
     let mut world = HittableList::new();
-    world.add(Arc::new(BvhNode::from(boxes_1)));
+    world.add(Arc::new(Bvh::from(boxes_1)));
 
     let light = Arc::new(DiffuseLight::from_rgb(7., 7., 7.));
-    world.add(Arc::new(Quad::new(
+    let light_quad: ArcHittable = Arc::new(Quad::new(
         Vec3::new(123., 554., 147.),
         Vec3::new(300., 0., 0.),
         Vec3::new(0., 0., 265.),
         light,
         Shape::Square,
-    )));
+    ));
+    world.add(light_quad.clone());
 
     let center_1 = Vec3::new(400., 400., 200.);
     let center_2 = center_1 + Vec3::new(30., 0., 0.);
@@ -113,7 +122,7 @@ pub fn final_scene() {
         emat,
     )));
 
-    let perlin_texture = NoiseTexture::new(0.2);
+    let perlin_texture = NoiseTexture::new(0.2, seed);
     world.add(Arc::new(Sphere::new(
         Vec3::new(220.0, 280.0, 300.0),
         80.0,
@@ -124,18 +133,20 @@ pub fn final_scene() {
     let white = Arc::new(Lambertian::from_rgb(0.73, 0.73, 0.73));
     for _ in 0..1000 {
         boxes_2.add(Arc::new(Sphere::new(
-            Vec3::random_min_max(0., 165.),
+            rng.vec3(0., 165.),
             10.,
             white.clone(),
         )))
     }
 
-    world.add(Arc::new(Translate::new(
-        Arc::new(RotateY::new(Arc::new(BvhNode::from(boxes_2)), 15.)),
-        Vec3::new(-100., 270., 395.),
+    world.add(Arc::new(Transform::new(
+        Arc::new(Bvh::from(boxes_2)),
+        Mat4::rotation_y(15.).translate(Vec3::new(-100., 270., 395.)),
     )));
 
-    if let Err(e) = camera.render(world) {
-        eprintln!("Failed while rendering with error: {e}")
+    Scene {
+        camera,
+        world: Arc::new(world),
+        lights: HittableList::from_vec(vec![light_quad]),
     }
 }
@@ -0,0 +1,222 @@
+//! Procedural daylight: the analytic sky model of Preetham, Shirley and Smits
+//! (1999) with a sun disk dimmed by the atmosphere.
+//!
+//! The sky is given by its luminance and chromaticity at the zenith, spread
+//! over the dome by the Perez distribution. The sun is a small disk whose
+//! color comes from the Rayleigh and aerosol extinction along its path
+//! through the air.
+
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use crate::{
+    color::Color,
+    environment::Environment,
+    onb::Onb,
+    random,
+    vector::{dot, unit_vector, Vec3},
+};
+
+/// Angular radius of the sun, in radians
+const SUN_RADIUS: f64 = 0.00465;
+
+/// Luminance of the sun outside the atmosphere, in kcd/m²
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+/// Sky luminances are in kcd/m², scaled to the range of the other lights
+const SCALE: f64 = 0.03;
+
+/// Coefficients A to E of the Perez distribution
+type Perez = [f64; 5];
+
+#[derive(Debug)]
+pub struct Sky {
+    sun_direction: Vec3,
+    /// Luminance Y and chromaticity x, y at the zenith
+    zenith: [f64; 3],
+    perez: [Perez; 3],
+    /// Perez distribution at the zenith, which is scaled to `zenith`
+    perez_zenith: [f64; 3],
+    sun_radiance: Color,
+    intensity: f64,
+}
+
+impl Sky {
+    /// Sky with the sun `elevation` degrees above the horizon and `azimuth`
+    /// degrees from -z towards +x. `turbidity` is the haziness of the air,
+    /// from 2 for a very clear sky to about 10 for a hazy one.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        let sun_direction = Vec3::new(
+            azimuth.sin() * elevation.cos(),
+            elevation.sin(),
+            -azimuth.cos() * elevation.cos(),
+        );
+
+        // The model is only valid for a sun above the horizon
+        let t = turbidity.max(1.0);
+        let theta_s = (FRAC_PI_2 - elevation).clamp(0.0, FRAC_PI_2 - 0.01);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let polynomial = |c: [[f64; 4]; 3]| {
+            let [a, b, c] = c.map(|[k3, k2, k1, k0]| {
+                k3 * theta_s.powi(3) + k2 * theta_s.powi(2) + k1 * theta_s + k0
+            });
+            t * t * a + t * b + c
+        };
+        let x = polynomial([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = polynomial([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let perez_zenith = perez.map(|coefficients| perez_function(coefficients, 1.0, theta_s));
+
+        // Sunlight is dimmed by the air mass it crosses (Kasten and Young)
+        let sun_radiance = if elevation > -SUN_RADIUS {
+            let zenith_degrees = theta_s.to_degrees();
+            let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+            let transmittance = |wavelength: f64| {
+                (-air_mass * optical_depth(wavelength, t)).exp() * SUN_LUMINANCE * SCALE
+            };
+            Color::new(
+                transmittance(0.68),
+                transmittance(0.55),
+                transmittance(0.44),
+            )
+        } else {
+            Color::BLACK
+        };
+
+        Self {
+            sun_direction,
+            zenith: [luminance.max(0.0), x, y],
+            perez,
+            perez_zenith,
+            sun_radiance,
+            intensity: 1.0,
+        }
+    }
+
+    /// Scale of the radiance of the sky and sun
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Cosine of the angular radius of the sun
+    fn cos_sun_radius() -> f64 {
+        SUN_RADIUS.cos()
+    }
+
+    /// Probability of sampling the sun rather than the whole sky
+    fn sun_probability(&self) -> f64 {
+        if self.sun_radiance.luminance() > 0.0 {
+            0.5
+        } else {
+            0.0
+        }
+    }
+
+    fn sky_radiance(&self, direction: Vec3) -> Color {
+        // Below the horizon the sky is continued with its value at the horizon
+        let cos_theta = direction.y.max(0.0);
+        let cos_gamma = dot(direction, self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * perez_function(self.perez[i], cos_theta, gamma) / self.perez_zenith[i]
+        });
+        if y <= 0.0 {
+            return Color::BLACK;
+        }
+
+        // xyY to linear sRGB
+        let big_x = x * luminance / y;
+        let big_z = (1.0 - x - y) * luminance / y;
+        let rgb = Color::new(
+            3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+            -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+            0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+        );
+        SCALE * Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+}
+
+/// Perez distribution for a direction `theta` from the zenith and `gamma`
+/// from the sun
+fn perez_function([a, b, c, d, e]: Perez, cos_theta: f64, gamma: f64) -> f64 {
+    let cos_gamma = gamma.cos();
+    (1.0 + a * (b / cos_theta.max(1e-3)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+/// Optical depth of the whole atmosphere at the zenith, for a wavelength in
+/// micrometers: Rayleigh scattering and Ångström's aerosol extinction
+fn optical_depth(wavelength: f64, turbidity: f64) -> f64 {
+    let rayleigh = 0.008735 * wavelength.powf(-4.08);
+    let beta = 0.04608 * turbidity - 0.04586;
+    rayleigh + beta * wavelength.powf(-1.3)
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let direction = unit_vector(direction);
+        let mut radiance = self.sky_radiance(direction);
+        if dot(direction, self.sun_direction) >= Self::cos_sun_radius() {
+            radiance += self.sun_radiance;
+        }
+        self.intensity * radiance
+    }
+
+    fn pdf_value(&self, direction: Vec3) -> f64 {
+        let direction = unit_vector(direction);
+        let sun_probability = self.sun_probability();
+        let mut pdf = (1.0 - sun_probability) / (4.0 * PI);
+        if dot(direction, self.sun_direction) >= Self::cos_sun_radius() {
+            pdf += sun_probability / (2.0 * PI * (1.0 - Self::cos_sun_radius()));
+        }
+        pdf
+    }
+
+    /// The sun disk half of the time, otherwise any direction
+    fn random(&self) -> Vec3 {
+        if random::float() >= self.sun_probability() {
+            return Vec3::random_unit_vector();
+        }
+
+        // Uniform direction in the cone of the sun
+        let cos_theta = 1.0 - random::float() * (1.0 - Self::cos_sun_radius());
+        let phi = 2.0 * PI * random::float();
+        Onb::new(self.sun_direction).to_world(Vec3::from_spherical(cos_theta, phi))
+    }
+}
@@ -1,10 +1,15 @@
-use std::{f64::consts::PI, sync::Arc};
+use std::{
+    f64::consts::{PI, SQRT_2},
+    sync::Arc,
+};
 
 use crate::{
     boundind_box::BoundingBox,
     hittables::{HitRecord, Hittable},
     interval::Interval,
     material::Material,
+    onb::Onb,
+    random,
     ray::Ray,
     vector::{dot, Vec3},
 };
@@ -67,8 +72,10 @@ impl Sphere {
         }
     }
 
+    /// Spheres in motion go from their first center at time 0 to the second
+    /// one at time 1 and stay there
     pub fn sphere_center(&self, time: f64) -> Vec3 {
-        self.center + time * self.direction.unwrap_or_default()
+        self.center + time.clamp(0.0, 1.0) * self.direction.unwrap_or_default()
     }
 
     // Get texture mapped coordinates of a given `point` on the sphere
@@ -80,6 +87,23 @@ impl Sphere {
 
         (u, v)
     }
+
+    /// Derivatives ∂p/∂u and ∂p/∂v at the point with outward `normal`
+    fn tangents(&self, normal: Vec3) -> (Vec3, Vec3) {
+        let Vec3 { x, y, z } = normal;
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(z, 0.0, -x);
+
+        // ∂p/∂v points from the south to the north pole, it is undefined at
+        // the poles themselves
+        let sin_theta = (x * x + z * z).sqrt();
+        let dpdv = if sin_theta > 1e-9 {
+            PI * self.radius * Vec3::new(-x * y / sin_theta, sin_theta, -y * z / sin_theta)
+        } else {
+            PI * self.radius * Vec3::new(1.0, 0.0, 0.0)
+        };
+
+        (dpdu, dpdv)
+    }
 }
 
 impl Hittable for Sphere {
@@ -109,16 +133,56 @@ impl Hittable for Sphere {
 
         let outward_normal = (ray.at(root) - center) / self.radius;
 
-        Some(HitRecord::new(
-            ray,
-            outward_normal,
-            Self::get_uv(outward_normal),
-            root,
-            self.material.clone(),
-        ))
+        let (tangent, bitangent) = self.tangents(outward_normal);
+        // u goes around the equator and v from pole to pole
+        let uv_density = 1.0 / (PI * SQRT_2 * self.radius.abs());
+        Some(
+            HitRecord::new(
+                ray,
+                outward_normal,
+                Self::get_uv(outward_normal),
+                root,
+                self.material.clone(),
+            )
+            .with_tangents(tangent, bitangent)
+            .with_uv_density(uv_density),
+        )
     }
 
     fn bounding_box(&self) -> BoundingBox {
         self.bbox.clone()
     }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
+        let ray = Ray::new(origin, direction, time);
+        if self.hit(&ray, Interval::positive()).is_none() {
+            return 0.0;
+        }
+
+        let distance_squared = (self.sphere_center(time) - origin).len_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI);
+        }
+
+        // Directions are uniform inside the cone subtended by the sphere
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: Vec3, time: f64) -> Vec3 {
+        let direction = self.sphere_center(time) - origin;
+        let distance_squared = direction.len_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector();
+        }
+
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let z = 1.0 + random::float() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random::float();
+
+        // Cone of directions around the sphere center
+        Onb::new(direction).to_world(Vec3::from_spherical(z, phi))
+    }
 }
@@ -1,12 +1,18 @@
 use std::sync::Arc;
 
-use crate::{color::Color, image::Image, interval::Interval, perlin::Perlin, vector::Vec3};
+use crate::{boundind_box::BoundingBox, color::Color, image::Image, perlin::Perlin, vector::Vec3};
 
 pub type ArcTexture = Arc<dyn Texture + Send + Sync>;
 
 pub trait Texture {
     // uv are the texture coordinates
     fn value(&self, uv: (f64, f64), point: Vec3) -> Color;
+
+    /// Value averaged over `footprint`, the width of the area seen by the
+    /// ray in texture coordinates. Only image textures need to filter.
+    fn filtered(&self, uv: (f64, f64), point: Vec3, _footprint: f64) -> Color {
+        self.value(uv, point)
+    }
 }
 
 // annoying orphan rule
@@ -39,6 +45,33 @@ impl Texture for SolidColor {
     }
 }
 
+/// Single channel of another texture as a grey value, e.g. the metalness
+/// stored in the blue channel of a glTF metallic-roughness texture
+pub struct Channel {
+    texture: ArcTexture,
+    channel: usize,
+}
+
+impl Channel {
+    /// `channel` is 0, 1 or 2 for red, green or blue
+    pub fn new(texture: ArcTexture, channel: usize) -> Self {
+        assert!(channel < 3);
+        Self { texture, channel }
+    }
+}
+
+impl Texture for Channel {
+    fn value(&self, uv: (f64, f64), point: Vec3) -> Color {
+        let value = self.texture.value(uv, point)[self.channel];
+        Color::new(value, value, value)
+    }
+
+    fn filtered(&self, uv: (f64, f64), point: Vec3, footprint: f64) -> Color {
+        let value = self.texture.filtered(uv, point, footprint)[self.channel];
+        Color::new(value, value, value)
+    }
+}
+
 pub struct Checker {
     inv_scale: f64,
     even: ArcTexture,
@@ -75,33 +108,214 @@ impl Texture for Checker {
 
         self.odd.value(uv, point)
     }
+
+    fn filtered(&self, uv: (f64, f64), point: Vec3, footprint: f64) -> Color {
+        let x = (self.inv_scale * point.x).floor() as i32;
+        let y = (self.inv_scale * point.y).floor() as i32;
+        let z = (self.inv_scale * point.z).floor() as i32;
+
+        if (x + y + z) % 2 == 0 {
+            return self.even.filtered(uv, point, footprint);
+        }
+
+        self.odd.filtered(uv, point, footprint)
+    }
+}
+
+/// How texels are combined into the value of a texture
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Closest texel
+    #[default]
+    Nearest,
+    /// Blend of the 4 closest texels
+    Bilinear,
+    /// Bilinear lookups in the two mip levels closest to the footprint of
+    /// the ray, blended together
+    Trilinear,
+}
+
+/// How texture coordinates outside of [0, 1] are mapped onto the image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Wrap {
+    /// Tile the image
+    Repeat,
+    /// Tile the image, flipping every other copy
+    Mirror,
+    /// Extend the border texels, so that the poles of a sphere do not blend
+    /// the top and bottom rows together
+    #[default]
+    Clamp,
+}
+
+impl Wrap {
+    /// Map texel index `i` into `0..size`
+    fn apply(self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let i = match self {
+            Self::Repeat => i.rem_euclid(size),
+            Self::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+            Self::Clamp => i.clamp(0, size - 1),
+        };
+        i as usize
+    }
+}
+
+/// Transformation of the texture coordinates before the lookup: scaled,
+/// then rotated counterclockwise around the origin and finally offset
+#[derive(Debug, Clone, Copy)]
+pub struct UvTransform {
+    pub scale: (f64, f64),
+    pub offset: (f64, f64),
+    /// Angle in degrees
+    pub rotation: f64,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self {
+            scale: (1.0, 1.0),
+            offset: (0.0, 0.0),
+            rotation: 0.0,
+        }
+    }
+}
+
+impl UvTransform {
+    pub fn apply(&self, uv: (f64, f64)) -> (f64, f64) {
+        let (u, v) = (self.scale.0 * uv.0, self.scale.1 * uv.1);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        (
+            cos * u - sin * v + self.offset.0,
+            sin * u + cos * v + self.offset.1,
+        )
+    }
+
+    /// Largest stretch of lengths in texture space
+    fn max_scale(&self) -> f64 {
+        self.scale.0.abs().max(self.scale.1.abs())
+    }
 }
 
 pub struct ImageTexture {
     img: Image,
+    filter: Filter,
+    wrap: Wrap,
+    transform: UvTransform,
 }
 
 impl ImageTexture {
     pub fn new(filename: &str) -> Self {
+        Self::from_image(Image::new(filename))
+    }
+
+    /// Load the image at `path` instead of the texture directory
+    pub fn open(path: &str) -> Self {
+        Self::from_image(Image::open(path))
+    }
+
+    pub fn from_image(img: Image) -> Self {
         Self {
-            img: Image::new(filename),
+            img,
+            filter: Filter::default(),
+            wrap: Wrap::default(),
+            transform: UvTransform::default(),
         }
     }
+
+    /// Use the values stored in the image file, see [`Image::raw`]
+    pub fn raw(mut self) -> Self {
+        self.img = self.img.raw();
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: UvTransform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    fn nearest(&self, u: f64, v: f64) -> Color {
+        let (width, height) = self.img.level_size(0);
+        let i = self.wrap.apply((u * width as f64).floor() as i64, width);
+        let j = self.wrap.apply((v * height as f64).floor() as i64, height);
+        self.img.texel(0, i, j)
+    }
+
+    fn bilinear(&self, level: usize, u: f64, v: f64) -> Color {
+        let (width, height) = self.img.level_size(level);
+        // Texel centers are at half integer coordinates
+        let x = u * width as f64 - 0.5;
+        let y = v * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let (i0, j0) = (x0 as i64, y0 as i64);
+        let [i0, i1] = [i0, i0 + 1].map(|i| self.wrap.apply(i, width));
+        let [j0, j1] = [j0, j0 + 1].map(|j| self.wrap.apply(j, height));
+
+        let top = (1.0 - tx) * self.img.texel(level, i0, j0) + tx * self.img.texel(level, i1, j0);
+        let bottom =
+            (1.0 - tx) * self.img.texel(level, i0, j1) + tx * self.img.texel(level, i1, j1);
+        (1.0 - ty) * top + ty * bottom
+    }
+
+    fn trilinear(&self, u: f64, v: f64, footprint: f64) -> Color {
+        let (width, height) = self.img.level_size(0);
+        let texels = footprint * width.max(height) as f64;
+        let max_level = (self.img.levels() - 1) as f64;
+        // Level where the footprint covers about one texel
+        let level = if texels > 1.0 {
+            texels.log2().min(max_level)
+        } else {
+            0.0
+        };
+
+        let lower = level.floor();
+        let t = level - lower;
+        let color = self.bilinear(lower as usize, u, v);
+        if t <= 0.0 {
+            return color;
+        }
+        (1.0 - t) * color + t * self.bilinear(lower as usize + 1, u, v)
+    }
 }
 
 impl Texture for ImageTexture {
-    fn value(&self, uv: (f64, f64), _: Vec3) -> Color {
+    fn value(&self, uv: (f64, f64), point: Vec3) -> Color {
+        self.filtered(uv, point, 0.0)
+    }
+
+    fn filtered(&self, uv: (f64, f64), _: Vec3, footprint: f64) -> Color {
         if self.img.height <= 0 {
             return Color::new(0.0, 1.0, 1.0);
         }
 
-        let u = Interval::new(0.0, 1.0).clamp(uv.0);
-        let v = 1.0 - Interval::new(0.0, 1.0).clamp(uv.1);
-
-        let i = (u * self.img.width as f64) as usize;
-        let j = (v * self.img.height as f64) as usize;
+        // Images are stored top to bottom
+        let (u, v) = self.transform.apply(uv);
+        let v = 1.0 - v;
 
-        self.img.pixel_color(i, j)
+        match self.filter {
+            Filter::Nearest => self.nearest(u, v),
+            Filter::Bilinear => self.bilinear(0, u, v),
+            Filter::Trilinear => self.trilinear(u, v, footprint * self.transform.max_scale()),
+        }
     }
 }
 
@@ -112,9 +326,9 @@ pub struct NoiseTexture {
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f64) -> Self {
+    pub fn new(scale: f64, seed: u64) -> Self {
         Self {
-            noise: Perlin::new(256),
+            noise: Perlin::new(256, seed),
             scale,
         }
     }
@@ -128,3 +342,95 @@ impl Texture for NoiseTexture {
         Color::new(0.5, 0.5, 0.5) * (1.0 + (angle + phase).sin())
     }
 }
+
+/// Fractal Perlin noise clamped to [0, 1], grey puffs separated by empty
+/// space. Meant as the density of smoke and clouds.
+pub struct FbmTexture {
+    noise: Perlin,
+    // Frequency of the first octave
+    scale: f64,
+    octaves: u32,
+}
+
+impl FbmTexture {
+    pub fn new(scale: f64, octaves: u32, seed: u64) -> Self {
+        Self {
+            noise: Perlin::new(256, seed),
+            scale,
+            octaves,
+        }
+    }
+}
+
+impl Texture for FbmTexture {
+    fn value(&self, _: (f64, f64), point: Vec3) -> Color {
+        let value = self.noise.fbm(self.scale * point, self.octaves);
+        Color::WHITE * value.clamp(0.0, 1.0)
+    }
+}
+
+/// Grey values on a regular 3D grid spanning a box, trilinearly
+/// interpolated between the voxel centers and clamped at the edges. Zero
+/// outside of the box.
+pub struct GridTexture {
+    size: [usize; 3],
+    // x varies fastest, then y, then z
+    values: Vec<f64>,
+    bbox: BoundingBox,
+}
+
+impl GridTexture {
+    /// `values` holds `size[0] * size[1] * size[2]` values spread over the
+    /// box with corners `a` and `b`
+    pub fn new(size: [usize; 3], values: Vec<f64>, a: Vec3, b: Vec3) -> Self {
+        assert!(size.iter().all(|&n| n > 0));
+        assert_eq!(values.len(), size.iter().product::<usize>());
+        Self {
+            size,
+            values,
+            bbox: BoundingBox::from_extrema(a, b),
+        }
+    }
+
+    fn voxel(&self, [x, y, z]: [usize; 3]) -> f64 {
+        self.values[(z * self.size[1] + y) * self.size[0] + x]
+    }
+}
+
+impl Texture for GridTexture {
+    fn value(&self, _: (f64, f64), point: Vec3) -> Color {
+        let axes = [&self.bbox.x, &self.bbox.y, &self.bbox.z];
+        let coordinates = [point.x, point.y, point.z];
+        if (0..3).any(|i| !axes[i].contains(coordinates[i])) {
+            return Color::BLACK;
+        }
+
+        // Voxel below the point on each axis and the offset from its center
+        let mut cell = [0; 3];
+        let mut offset = [0.0; 3];
+        for i in 0..3 {
+            let n = self.size[i];
+            let t = (coordinates[i] - axes[i].min) / axes[i].span().max(f64::MIN_POSITIVE);
+            let x = (t * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            cell[i] = (x as usize).min(n.saturating_sub(2));
+            offset[i] = x - cell[i] as f64;
+        }
+
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut voxel = cell;
+            for i in 0..3 {
+                if corner >> i & 1 == 1 {
+                    voxel[i] = (voxel[i] + 1).min(self.size[i] - 1);
+                    weight *= offset[i];
+                } else {
+                    weight *= 1.0 - offset[i];
+                }
+            }
+            value += weight * self.voxel(voxel);
+        }
+
+        Color::WHITE * value
+    }
+}
@@ -12,6 +12,9 @@ use crate::{
     vector::Vec3,
 };
 
+/// Gap left after a boundary hit before looking for the next one
+const BOUNDARY_EPSILON: f64 = 0.0001;
+
 pub struct ConstantMedium {
     boundary: ArcHittable,
     neg_inv_density: f64,
@@ -19,7 +22,7 @@ pub struct ConstantMedium {
 }
 
 impl ConstantMedium {
-    pub fn _new(boundary: ArcHittable, density: f64, texture: ArcTexture) -> Self {
+    pub fn new(boundary: ArcHittable, density: f64, texture: ArcTexture) -> Self {
         let neg_inv_density = -1.0 / density;
         let phase_function = Arc::new(Isotropic::new(texture));
         Self {
@@ -38,45 +41,211 @@ impl ConstantMedium {
             phase_function,
         }
     }
+
+    /// Scatter with another phase function than the isotropic one, e.g.
+    /// [`HenyeyGreenstein`](crate::material::HenyeyGreenstein)
+    pub fn with_phase_function(mut self, phase_function: ArcMaterial) -> Self {
+        self.phase_function = phase_function;
+        self
+    }
 }
 
 impl Hittable for ConstantMedium {
     fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
-        let mut record_1 = self.boundary.hit(ray, Interval::_universe())?;
+        let ray_len = ray.direction.len();
+
+        // The density is the same everywhere, a free flight sampled in one
+        // part of the boundary can be resampled in the next one
+        for inside in Segments::new(self.boundary.as_ref(), ray, interval) {
+            let distance_inside_boundary = (inside.max - inside.min) * ray_len;
+            let hit_distance = self.neg_inv_density * random::float().ln();
+
+            if hit_distance <= distance_inside_boundary {
+                return Some(scattering(
+                    ray,
+                    inside.min + hit_distance / ray_len,
+                    self.phase_function.clone(),
+                ));
+            }
+        }
+
+        None
+    }
+
+    fn hit_surface(&self, _: &Ray, _: Interval) -> Option<HitRecord> {
+        None
+    }
+
+    // Known exactly, e^(-density * length)
+    fn transmittance(&self, ray: &Ray, interval: Interval) -> f64 {
+        let ray_len = ray.direction.len();
+        Segments::new(self.boundary.as_ref(), ray, interval)
+            .map(|inside| ((inside.max - inside.min) * ray_len / self.neg_inv_density).exp())
+            .product()
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.boundary.bounding_box()
+    }
+}
+
+/// Medium whose density varies in space, e.g. smoke or clouds. Collisions
+/// are found with delta tracking: free flights are sampled with the
+/// maximum density and each collision is only accepted with the ratio of
+/// the actual density to the maximum, the others are null collisions.
+/// Shadow rays use ratio tracking instead, going through all the tentative
+/// collisions and multiplying the transmittance by the chance of each one
+/// being a null collision, which is much less noisy than all or nothing.
+pub struct HeterogeneousMedium {
+    boundary: ArcHittable,
+    /// Grey value in [0, 1] scaling `max_density` at every point
+    density: ArcTexture,
+    max_density: f64,
+    phase_function: ArcMaterial,
+}
+
+impl HeterogeneousMedium {
+    /// Medium with density `max_density` times the value of `density`
+    /// (clamped to [0, 1]) inside of `boundary`, which may be any closed
+    /// surface
+    pub fn new(
+        boundary: ArcHittable,
+        density: ArcTexture,
+        max_density: f64,
+        texture: ArcTexture,
+    ) -> Self {
+        Self {
+            boundary,
+            density,
+            max_density,
+            phase_function: Arc::new(Isotropic::new(texture)),
+        }
+    }
 
-        let mut record_2 = self
-            .boundary
-            .hit(ray, Interval::with_min(record_1.distance + 0.0001))?;
+    /// Scatter with another phase function than the isotropic one
+    pub fn with_phase_function(mut self, phase_function: ArcMaterial) -> Self {
+        self.phase_function = phase_function;
+        self
+    }
 
-        record_1.distance = record_1.distance.max(interval.min);
-        record_2.distance = record_2.distance.min(interval.max);
+    fn density_at(&self, point: Vec3) -> f64 {
+        self.density
+            .value((0.0, 0.0), point)
+            .luminance()
+            .clamp(0.0, 1.0)
+    }
+}
 
-        if record_1.distance >= record_2.distance {
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        if self.max_density <= 0.0 {
             return None;
         }
+        let neg_inv_majorant = -1.0 / (self.max_density * ray.direction.len());
 
-        if record_1.distance < 0.0 {
-            record_1.distance = 0.0
+        for inside in Segments::new(self.boundary.as_ref(), ray, interval) {
+            let mut distance = inside.min;
+            loop {
+                distance += neg_inv_majorant * (1.0 - random::float()).ln();
+                if distance >= inside.max {
+                    break;
+                }
+                if random::float() < self.density_at(ray.at(distance)) {
+                    return Some(scattering(ray, distance, self.phase_function.clone()));
+                }
+            }
         }
 
-        let ray_len = ray.direction.len();
-        let distance_inside_boundary = (record_2.distance - record_1.distance) * ray_len;
-        let hit_distance = self.neg_inv_density * random::float().ln();
+        None
+    }
 
-        if hit_distance > distance_inside_boundary {
-            return None;
+    fn hit_surface(&self, _: &Ray, _: Interval) -> Option<HitRecord> {
+        None
+    }
+
+    fn transmittance(&self, ray: &Ray, interval: Interval) -> f64 {
+        if self.max_density <= 0.0 {
+            return 1.0;
         }
+        let neg_inv_majorant = -1.0 / (self.max_density * ray.direction.len());
 
-        Some(HitRecord::new(
-            ray,
-            Vec3::X, // arbitrary
-            (0.0, 0.0),
-            record_1.distance + hit_distance / ray_len,
-            self.phase_function.clone(),
-        ))
+        let mut transmittance = 1.0;
+        for inside in Segments::new(self.boundary.as_ref(), ray, interval) {
+            let mut distance = inside.min;
+            loop {
+                distance += neg_inv_majorant * (1.0 - random::float()).ln();
+                if distance >= inside.max {
+                    break;
+                }
+                transmittance *= 1.0 - self.density_at(ray.at(distance));
+            }
+        }
+        transmittance
     }
 
     fn bounding_box(&self) -> BoundingBox {
         self.boundary.bounding_box()
     }
 }
+
+/// Collision with the medium at `distance` along the ray
+fn scattering(ray: &Ray, distance: f64, phase_function: ArcMaterial) -> HitRecord {
+    HitRecord::new(
+        ray,
+        Vec3::X, // arbitrary
+        (0.0, 0.0),
+        distance,
+        phase_function,
+    )
+}
+
+/// Parts of a ray inside of a closed boundary and within an interval, in
+/// order. Every crossing of the boundary toggles between inside and
+/// outside, so it does not need to be convex.
+struct Segments<'a> {
+    boundary: &'a (dyn Hittable + Send + Sync),
+    ray: &'a Ray,
+    interval: Interval,
+    // Where to look for the next entry into the boundary
+    next: Option<f64>,
+}
+
+impl<'a> Segments<'a> {
+    fn new(boundary: &'a (dyn Hittable + Send + Sync), ray: &'a Ray, interval: Interval) -> Self {
+        Self {
+            boundary,
+            ray,
+            interval,
+            // A ray starting inside the boundary still crosses it earlier
+            // along its line
+            next: Some(f64::NEG_INFINITY),
+        }
+    }
+}
+
+impl Iterator for Segments<'_> {
+    type Item = Interval;
+
+    fn next(&mut self) -> Option<Interval> {
+        loop {
+            let from = self.next.take()?;
+            let entry = self.boundary.hit(self.ray, Interval::with_min(from))?;
+            if entry.distance >= self.interval.max {
+                return None;
+            }
+            let exit = self.boundary.hit(
+                self.ray,
+                Interval::with_min(entry.distance + BOUNDARY_EPSILON),
+            )?;
+            self.next = Some(exit.distance + BOUNDARY_EPSILON);
+
+            let inside = Interval::new(
+                entry.distance.max(self.interval.min),
+                exit.distance.min(self.interval.max),
+            );
+            if inside.min < inside.max {
+                return Some(inside);
+            }
+        }
+    }
+}
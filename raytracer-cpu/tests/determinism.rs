@@ -0,0 +1,107 @@
+//! Renders only depend on their seed: not on how many threads rayon runs
+//! the tiles on, even when the reconstruction filter makes tiles overlap,
+//! nor on anything else than the seed when building scenes from random
+//! numbers.
+
+use raytracer_cpu::{
+    camera::Camera,
+    filter::{Filter, FilterKind},
+    hittables::Hittable,
+    interval::Interval,
+    random,
+    ray::Ray,
+    sampler::SamplerKind,
+    scenes::{self, Scene},
+    vector::Vec3,
+};
+
+/// Bits of every pixel of a small Cornell box rendered on `threads` threads
+fn render(threads: usize, seed: u64, sampler: SamplerKind, filter: Filter) -> Vec<[u64; 3]> {
+    let Scene {
+        mut camera,
+        world,
+        lights,
+    } = scenes::cornell_box();
+    // Several tiles of 32 pixels
+    camera.image_width = 72;
+    camera.samples = 4;
+    camera.max_depth = 8;
+    camera.seed = seed;
+    camera.sampler = sampler;
+    camera.filter = filter;
+    let camera = Camera::new(camera);
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    let film = pool.install(|| camera.render(world, &lights));
+
+    film.colors()
+        .iter()
+        .map(|c| [c.x.to_bits(), c.y.to_bits(), c.z.to_bits()])
+        .collect()
+}
+
+#[test]
+fn render_does_not_depend_on_threads() {
+    for sampler in [SamplerKind::Independent, SamplerKind::Sobol] {
+        let box_filter = Filter::default();
+        let single = render(1, 7, sampler, box_filter);
+        assert_eq!(single, render(4, 7, sampler, box_filter), "{sampler:?}");
+        assert_eq!(single, render(3, 7, sampler, box_filter), "{sampler:?}");
+        assert_ne!(single, render(4, 8, sampler, box_filter), "{sampler:?}");
+    }
+}
+
+#[test]
+fn overlapping_tiles_do_not_depend_on_threads() {
+    // Wide filters spread samples over neighbouring tiles
+    for kind in [FilterKind::Gaussian, FilterKind::Lanczos] {
+        let filter = Filter::new(kind);
+        assert!(filter.margin() > 0);
+        let single = render(1, 7, SamplerKind::Independent, filter);
+        for threads in [2, 3, 4, 8] {
+            let render = render(threads, 7, SamplerKind::Independent, filter);
+            assert_eq!(single, render, "{kind:?} on {threads} threads");
+        }
+    }
+}
+
+/// Hits of a fan of rays over the ground of the bouncing spheres scene
+/// built with `seed`
+fn bouncing_spheres_hits(seed: u64) -> Vec<Option<[u64; 4]>> {
+    let world = scenes::bouncing_spheres(seed).world;
+
+    let origin = Vec3::new(0.0, 3.0, 0.0);
+    let mut hits = Vec::new();
+    for i in 0..64 {
+        for j in 0..64 {
+            let direction = Vec3::new(i as f64 - 31.5, -3.0, j as f64 - 31.5);
+            let ray = Ray::new(origin, direction, 0.0);
+            hits.push(world.hit(&ray, Interval::positive()).map(|hit| {
+                [
+                    hit.distance.to_bits(),
+                    hit.normal.x.to_bits(),
+                    hit.normal.y.to_bits(),
+                    hit.normal.z.to_bits(),
+                ]
+            }));
+        }
+    }
+    hits
+}
+
+#[test]
+fn scenes_only_depend_on_seed() {
+    let hits = bouncing_spheres_hits(11);
+    assert_eq!(hits, bouncing_spheres_hits(11));
+    assert_ne!(hits, bouncing_spheres_hits(12));
+
+    // Whatever the thread drew before
+    random::seed(12);
+    random::float();
+    assert_eq!(hits, bouncing_spheres_hits(11));
+    let other_thread = std::thread::spawn(|| bouncing_spheres_hits(11));
+    assert_eq!(other_thread.join().unwrap(), hits);
+}
@@ -0,0 +1,258 @@
+//! OpenEXR images read by `Image::load_exr`: half and float channels in the
+//! alphabetical order of the file, grey Y channels and compressed blocks.
+//! Scene files fail to load when one of their images cannot be read.
+//!
+//! The fixtures are 37x20 images of half B, G and R channels, with R = x / 8,
+//! G = y / 8 and B = (x + y) / 16, ZIP compressed (two blocks of 16 and 4
+//! scanlines) and ZIPS compressed (one scanline per block).
+
+use raytracer_cpu::{image::Image, scenes::file};
+
+const HALF: u32 = 1;
+const FLOAT: u32 = 2;
+
+fn attribute(name: &str, kind: &str, value: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for text in [name, kind] {
+        bytes.extend_from_slice(text.as_bytes());
+        bytes.push(0);
+    }
+    bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(value);
+    bytes
+}
+
+/// Single part scanline image, one block of compressed data per scanline
+fn exr(channels: &[(&str, u32)], compression: u8, width: i32, blocks: &[Vec<u8>]) -> Vec<u8> {
+    let mut list = Vec::new();
+    for (name, pixel_type) in channels {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&pixel_type.to_le_bytes());
+        list.extend_from_slice(&[0; 4]);
+        list.extend_from_slice(&[1, 0, 0, 0, 1, 0, 0, 0]);
+    }
+    list.push(0);
+    let window: Vec<u8> = [0, 0, width - 1, blocks.len() as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&20000630u32.to_le_bytes());
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    bytes.extend(attribute("channels", "chlist", &list));
+    bytes.extend(attribute("compression", "compression", &[compression]));
+    bytes.extend(attribute("dataWindow", "box2i", &window));
+    bytes.extend(attribute("displayWindow", "box2i", &window));
+    bytes.push(0);
+
+    let mut offset = bytes.len() + 8 * blocks.len();
+    for block in blocks {
+        bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+        offset += 8 + block.len();
+    }
+    for (y, block) in blocks.iter().enumerate() {
+        bytes.extend_from_slice(&(y as i32).to_le_bytes());
+        bytes.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(block);
+    }
+    bytes
+}
+
+fn half(value: u16) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+fn float(value: f32) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+fn load(bytes: &[u8]) -> Result<Image, String> {
+    Image::load_exr(&mut &bytes[..])
+}
+
+fn assert_texel(image: &Image, x: usize, y: usize, expected: [f64; 3]) {
+    let texel = image.texel(0, x, y);
+    assert_eq!([texel.x, texel.y, texel.z], expected, "texel ({x}, {y})");
+}
+
+#[test]
+fn half_and_float_channels() {
+    // B is a float, G and R halves: 1.5, -2, the largest half, the smallest
+    // subnormal half and zero
+    let channels = [("B", FLOAT), ("G", HALF), ("R", HALF)];
+    let scanlines = [
+        [
+            float(0.25),
+            float(-8.0),
+            half(0x3e00),
+            half(0xc000),
+            half(0x7bff),
+            half(0x0001),
+        ],
+        [
+            float(1e-3),
+            float(0.0),
+            half(0x0000),
+            half(0x3c00),
+            half(0x8000),
+            half(0x3555),
+        ],
+    ]
+    .map(|line| line.concat());
+    let image = load(&exr(&channels, 0, 2, &scanlines)).unwrap();
+
+    assert_eq!((image.width, image.height), (2, 2));
+    assert_texel(&image, 0, 0, [65504.0, 1.5, 0.25]);
+    assert_texel(&image, 1, 0, [2f64.powi(-24), -2.0, -8.0]);
+    assert_texel(&image, 0, 1, [-0.0, 0.0, 1e-3f32 as f64]);
+    assert_texel(&image, 1, 1, [0.333251953125, 1.0, 0.0]);
+}
+
+#[test]
+fn y_channel_is_grey() {
+    // Channels the renderer has no use for are skipped
+    let channels = [("A", HALF), ("Y", FLOAT)];
+    let scanlines = [[half(0x3c00), half(0x3c00), float(0.5), float(3.0)].concat()];
+    let image = load(&exr(&channels, 0, 2, &scanlines)).unwrap();
+
+    assert_texel(&image, 0, 0, [0.5; 3]);
+    assert_texel(&image, 1, 0, [3.0; 3]);
+}
+
+/// Compress a block the way RLE images do: even bytes before odd ones,
+/// stored as differences, then runs of repeated bytes
+fn rle(data: &[u8]) -> Vec<u8> {
+    let mut reordered: Vec<u8> = data.iter().step_by(2).copied().collect();
+    reordered.extend(data.iter().skip(1).step_by(2));
+    let predicted: Vec<u8> = (0..reordered.len())
+        .map(|i| match i {
+            0 => reordered[0],
+            _ => reordered[i]
+                .wrapping_sub(reordered[i - 1])
+                .wrapping_add(128),
+        })
+        .collect();
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < predicted.len() {
+        let run = predicted[i..]
+            .iter()
+            .take(128)
+            .take_while(|&&b| b == predicted[i])
+            .count();
+        if run >= 3 {
+            out.extend_from_slice(&[run as u8 - 1, predicted[i]]);
+            i += run;
+        } else {
+            let len = (predicted.len() - i).min(3);
+            out.push((-(len as i8)) as u8);
+            out.extend_from_slice(&predicted[i..i + len]);
+            i += len;
+        }
+    }
+    out
+}
+
+#[test]
+fn rle_blocks() {
+    let channels = [("B", HALF), ("G", HALF), ("R", HALF)];
+    let values = |y: usize| -> Vec<u8> {
+        let b = (0..8).flat_map(|x| half(0x3c00 + (x * y) as u16));
+        let g = (0..8).flat_map(|_| half(0x4000));
+        let r = (0..8).flat_map(|x| half(if x < 4 { 0x3800 } else { 0xb800 }));
+        b.chain(g).chain(r).collect()
+    };
+
+    // Blocks that compression would not make smaller are stored as is
+    let scanlines: Vec<Vec<u8>> = (0..3).map(values).collect();
+    let mut blocks = vec![scanlines[0].clone()];
+    blocks.extend(scanlines[1..].iter().map(|line| rle(line)));
+    assert!(blocks[1].len() < scanlines[1].len());
+    let image = load(&exr(&channels, 1, 8, &blocks)).unwrap();
+
+    for y in 0..3 {
+        for x in 0..8 {
+            // Halves right above 1 are 2^-10 apart
+            let blue = 1.0 + (x * y) as f64 / 1024.0;
+            let red = if x < 4 { 0.5 } else { -0.5 };
+            assert_texel(&image, x, y, [red, 2.0, blue]);
+        }
+    }
+}
+
+fn fixture_path(name: &str) -> String {
+    format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+fn fixture(name: &str) -> Image {
+    Image::try_open(&fixture_path(name)).unwrap()
+}
+
+#[test]
+fn zip_and_zips_blocks() {
+    for name in ["gradient_zip.exr", "gradient_zips.exr"] {
+        let image = fixture(name);
+        assert_eq!((image.width, image.height), (37, 20), "{name}");
+        for y in 0..20 {
+            for x in 0..37 {
+                let expected = [x as f64 / 8.0, y as f64 / 8.0, (x + y) as f64 / 16.0];
+                assert_texel(&image, x, y, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn unreadable_environments_fail_scenes() {
+    // PIZ compression is not supported
+    let bytes = exr(&[("Y", HALF)], 4, 1, &[half(0x3c00)]);
+    assert!(load(&bytes).is_err());
+
+    let source = "[environment]\nfile = \"missing.exr\"\n";
+    match file::from_str(source, "missing.toml") {
+        Err(file::SceneError::Invalid { field, line, .. }) => {
+            assert_eq!((field.as_str(), line), ("environment.file", 1));
+        }
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("scene built with an unreadable environment"),
+    }
+}
+
+/// Scene of a sphere textured with the image `file`
+fn textured_scene(file: &str) -> String {
+    format!(
+        r#"
+[textures.gradient]
+type = "image"
+file = "{file}"
+
+[materials.gradient]
+type = "lambertian"
+texture = "gradient"
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "gradient"
+"#
+    )
+}
+
+#[test]
+fn unreadable_textures_fail_scenes() {
+    // Images are found next to the scene file
+    let path = fixture_path("scene.toml");
+    assert!(file::from_str(&textured_scene("gradient_zip.exr"), &path).is_ok());
+
+    match file::from_str(&textured_scene("missing.png"), &path) {
+        Err(file::SceneError::Invalid { field, line, .. }) => {
+            assert_eq!((field.as_str(), line), ("textures.gradient.file", 2));
+        }
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("scene built with an unreadable texture"),
+    }
+}
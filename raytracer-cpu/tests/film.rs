@@ -0,0 +1,156 @@
+//! Accumulation of samples in a `Film`: merging tiles at any offset and
+//! combining the error estimates of pixels.
+
+use raytracer_cpu::{
+    color::Color,
+    film::{Features, Film, Pixel},
+    filter::{Filter, FilterKind},
+};
+
+/// Film whose pixel (x, y) holds a single sample of value 1 + x + 10 y
+fn numbered(width: usize, height: usize) -> Film {
+    let mut film = Film::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let value = 1.0 + x as f64 + 10.0 * y as f64;
+            film.add_sample(
+                x,
+                y,
+                (0.5, 0.5),
+                Color::new(value, value, value),
+                Features::default(),
+                &Filter::default(),
+            );
+        }
+    }
+    film
+}
+
+/// Red channel of every pixel, row by row
+fn values(film: &Film) -> Vec<f64> {
+    film.colors().iter().map(|c| c.x).collect()
+}
+
+#[test]
+fn merge_at_clips_negative_offsets() {
+    let mut film = Film::new(4, 3);
+    film.merge_at(&numbered(3, 3), -1, -2);
+
+    // Only the bottom row of the tile, past its first column, lands inside
+    #[rustfmt::skip]
+    let expected = [
+        22.0, 23.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 0.0,
+    ];
+    assert_eq!(values(&film), expected);
+    let samples: u32 = film.pixels().iter().map(|p| p.samples).sum();
+    assert_eq!(samples, 2);
+}
+
+#[test]
+fn merge_at_clips_past_the_edges() {
+    let mut film = Film::new(4, 3);
+    film.merge_at(&numbered(3, 3), 2, 1);
+
+    #[rustfmt::skip]
+    let expected = [
+        0.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 2.0,
+        0.0, 0.0, 11.0, 12.0,
+    ];
+    assert_eq!(values(&film), expected);
+
+    // Tiles entirely outside, or larger than the film, are fine too
+    let mut film = Film::new(4, 3);
+    film.merge_at(&numbered(3, 3), -3, 0);
+    film.merge_at(&numbered(3, 3), 4, 0);
+    film.merge_at(&numbered(3, 3), 0, -3);
+    assert!(film.pixels().iter().all(|p| p.samples == 0));
+
+    let mut film = Film::new(2, 2);
+    film.merge_at(&numbered(4, 4), -1, -1);
+    assert_eq!(values(&film), [12.0, 13.0, 22.0, 23.0]);
+}
+
+#[test]
+fn merge_at_accumulates_overlapping_tiles() {
+    let mut film = Film::new(3, 1);
+    film.merge_at(&numbered(2, 1), 0, 0);
+    film.merge_at(&numbered(2, 1), 1, 0);
+
+    // The middle pixel averages 2 and 1
+    assert_eq!(values(&film), [1.0, 1.5, 2.0]);
+    assert_eq!(film.pixel(1, 0).samples, 2);
+}
+
+#[test]
+fn negative_lobes_do_not_light_pixels() {
+    // A single sample a quarter of a pixel right of the center of (2, 2)
+    let filter = Filter::new(FilterKind::Lanczos);
+    let color = Color::new(0.5, 1.0, 2.0);
+    let mut film = Film::new(5, 5);
+    film.add_sample(2, 2, (0.75, 0.5), color, Features::default(), &filter);
+
+    // Next to the sample, the weights are large and positive
+    for x in [2, 3] {
+        let pixel = film.pixel(x, 2);
+        assert!(pixel.weight > 0.2);
+        assert_eq!(pixel.color().z, 2.0, "pixel ({x}, 2)");
+    }
+
+    // Further in the row they are negative, a pixel away in the column they
+    // only differ from zero by rounding errors
+    for (x, y) in [(1, 2), (4, 2), (2, 1), (3, 3), (2, 4)] {
+        let pixel = film.pixel(x, y);
+        assert!(pixel.weight < 0.0 || pixel.weight.abs() < 1e-12);
+        assert_eq!(pixel.color().z, 0.0, "pixel ({x}, {y})");
+    }
+}
+
+fn pixel_of(samples: &[f64]) -> Pixel {
+    let mut pixel = Pixel::default();
+    for &value in samples {
+        let color = Color::new(value, value, value);
+        pixel.add(color, Features::default());
+        pixel.splat(color, 1.0);
+    }
+    pixel
+}
+
+#[test]
+fn pixel_merge_combines_variance() {
+    let samples = [0.5, 2.0, 0.25, 4.0, 1.0, 0.0, 3.5, 0.75, 1.25];
+    let whole = pixel_of(&samples);
+
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    assert!((whole.variance() - variance).abs() < 1e-12);
+
+    for split in 0..=samples.len() {
+        let mut merged = pixel_of(&samples[..split]);
+        merged.merge(&pixel_of(&samples[split..]));
+
+        assert_eq!(merged.samples, whole.samples);
+        assert!((merged.color().x - mean).abs() < 1e-12, "split {split}");
+        assert!(
+            (merged.variance() - variance).abs() < 1e-12,
+            "split {split}: {} instead of {variance}",
+            merged.variance()
+        );
+        assert!((merged.relative_error() - whole.relative_error()).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn pixel_variance_needs_two_samples() {
+    assert_eq!(Pixel::default().variance(), f64::INFINITY);
+    assert_eq!(pixel_of(&[1.0]).variance(), f64::INFINITY);
+
+    let mut pixel = pixel_of(&[1.0]);
+    pixel.merge(&Pixel::default());
+    assert_eq!(pixel.samples, 1);
+    pixel.merge(&pixel_of(&[3.0]));
+    assert_eq!(pixel.variance(), 2.0);
+}
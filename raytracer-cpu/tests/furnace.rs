@@ -0,0 +1,160 @@
+//! Energy conservation of the Lambertian material: its BRDF integrates to
+//! the albedo, its samples follow its density, and a white object lit by a
+//! uniform white environment disappears into it (the white furnace test),
+//! also when part of the environment is replaced by a light as bright.
+
+use std::{f64::consts::PI, sync::Arc};
+
+use raytracer_cpu::{
+    camera::{Camera, CameraConfig},
+    color::Color,
+    environment::Environment,
+    hittables::{HitRecord, HittableList},
+    material::{DiffuseLight, Lambertian, Material, Scatter},
+    quad::{Quad, Shape},
+    random,
+    ray::Ray,
+    sphere::Sphere,
+    vector::{dot, unit_vector, Vec3},
+};
+
+const SAMPLES: usize = 200_000;
+
+/// Hit on a surface facing +z, seen from straight above
+fn hit_record(material: Arc<Lambertian>) -> (Ray, HitRecord) {
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    let record = HitRecord::new(&ray, Vec3::Z, (0.0, 0.0), 1.0, material);
+    (ray, record)
+}
+
+#[test]
+fn lambertian_integrates_to_albedo() {
+    random::seed(1);
+    let material = Arc::new(Lambertian::from_rgb(0.5, 0.5, 0.5));
+    let (ray, record) = hit_record(material.clone());
+
+    // Uniform directions over the sphere, independent of the material
+    let (mut reflected, mut density) = (0.0, 0.0);
+    for _ in 0..SAMPLES {
+        let scattered = Ray::new(record.point, Vec3::random_unit_vector(), 0.0);
+        reflected += material.eval(&ray, &record, &scattered).y * 4.0 * PI;
+        density += material.pdf(&ray, &record, &scattered) * 4.0 * PI;
+    }
+
+    let reflected = reflected / SAMPLES as f64;
+    let density = density / SAMPLES as f64;
+    assert!((reflected - 0.5).abs() < 0.01, "reflected {reflected}");
+    assert!(
+        (density - 1.0).abs() < 0.02,
+        "density integrates to {density}"
+    );
+}
+
+#[test]
+fn lambertian_samples_follow_pdf() {
+    random::seed(2);
+    let material = Arc::new(Lambertian::from_rgb(1.0, 1.0, 1.0));
+    let (ray, record) = hit_record(material.clone());
+    let Some(Scatter::Pdf(pdf)) = material.scatter(&ray, &record) else {
+        panic!("Lambertian should scatter with a density");
+    };
+
+    // Mean cosine of a cosine weighted hemisphere is 2/3, its square 1/2
+    let (mut cosine, mut cosine_squared) = (0.0, 0.0);
+    for _ in 0..SAMPLES {
+        let direction = unit_vector(pdf.generate());
+        let cos_theta = dot(direction, Vec3::Z);
+        assert!(cos_theta >= 0.0, "sample below the surface");
+
+        let scattered = Ray::new(record.point, direction, 0.0);
+        let expected = material.pdf(&ray, &record, &scattered);
+        assert!((pdf.value(direction) - expected).abs() < 1e-9);
+
+        cosine += cos_theta;
+        cosine_squared += cos_theta * cos_theta;
+    }
+
+    let cosine = cosine / SAMPLES as f64;
+    let cosine_squared = cosine_squared / SAMPLES as f64;
+    assert!((cosine - 2.0 / 3.0).abs() < 0.005, "mean cosine {cosine}");
+    assert!(
+        (cosine_squared - 0.5).abs() < 0.005,
+        "mean squared cosine {cosine_squared}"
+    );
+}
+
+/// Same radiance from every direction, sampled uniformly
+#[derive(Debug)]
+struct Uniform;
+
+impl Environment for Uniform {
+    fn radiance(&self, _: Vec3) -> Color {
+        Color::WHITE
+    }
+
+    fn pdf_value(&self, _: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn random(&self) -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+}
+
+/// Mean and every pixel of a white sphere filling the whole image, lit by a
+/// uniform white environment and `lights`, which are also part of the world
+fn furnace(lights: HittableList) -> (f64, Vec<Color>) {
+    let mut world = HittableList::new();
+    world.add(Arc::new(Sphere::new(
+        Vec3::default(),
+        1.0,
+        Arc::new(Lambertian::from_rgb(1.0, 1.0, 1.0)),
+    )));
+    for light in lights.objects.iter() {
+        world.add(light.clone());
+    }
+
+    let camera = Camera::new(CameraConfig {
+        image_width: 16,
+        samples: 64,
+        max_depth: 50,
+        vfov: 6.0,
+        look_from: Vec3::new(0.0, 0.0, 10.0),
+        environment: Some(Arc::new(Uniform)),
+        seed: 3,
+        ..CameraConfig::default()
+    });
+    let colors = camera.render(world, &lights).colors();
+    let mean = colors.iter().map(|c| c.y).sum::<f64>() / colors.len() as f64;
+    (mean, colors)
+}
+
+#[test]
+fn white_furnace() {
+    let (mean, colors) = furnace(HittableList::new());
+    assert!((mean - 1.0).abs() < 0.01, "furnace returned {mean}");
+    for color in colors {
+        assert!((color.y - 1.0).abs() < 0.2, "pixel {color:?}");
+    }
+}
+
+#[test]
+fn white_furnace_with_a_light() {
+    // Huge light right behind the sphere, hidden from the camera and as
+    // bright as the environment it hides. Its density per solid angle is
+    // much lower than the environment's, so both must be accounted for when
+    // weighting a light hit by chance against light sampling.
+    let light = Quad::new(
+        Vec3::new(-100.0, -100.0, -1.5),
+        Vec3::new(200.0, 0.0, 0.0),
+        Vec3::new(0.0, 200.0, 0.0),
+        Arc::new(DiffuseLight::from_rgb(1.0, 1.0, 1.0)),
+        Shape::Square,
+    );
+    let (mean, colors) = furnace(HittableList::from_vec(vec![Arc::new(light)]));
+    // Weights adding up to more than one come out about 1% too bright
+    assert!((mean - 1.0).abs() < 0.005, "furnace returned {mean}");
+    for color in colors {
+        assert!((color.y - 1.0).abs() < 0.2, "pixel {color:?}");
+    }
+}
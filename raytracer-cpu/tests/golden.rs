@@ -0,0 +1,47 @@
+//! Golden values of a small Cornell box, rendered in memory. The reference
+//! averages come from a 4096 samples per pixel render of the same image,
+//! the test render is noisier so they are compared with a tolerance.
+
+use raytracer_cpu::{camera::Camera, color::Color, film::Film, scenes};
+
+const WIDTH: usize = 32;
+
+/// Average color of the pixels in columns `x` and rows `y`
+fn average(film: &Film, x: std::ops::Range<usize>, y: std::ops::Range<usize>) -> Color {
+    let mut sum = Color::BLACK;
+    let mut count = 0;
+    for row in y {
+        for column in x.clone() {
+            sum += film.pixel(column, row).color();
+            count += 1;
+        }
+    }
+    sum / count as f64
+}
+
+fn assert_close(name: &str, color: Color, expected: [f64; 3]) {
+    for (value, expected) in [color.x, color.y, color.z].into_iter().zip(expected) {
+        assert!(
+            (value - expected).abs() <= 0.06 * expected + 0.002,
+            "{name}: {color:?} instead of {expected:?}"
+        );
+    }
+}
+
+#[test]
+fn cornell_box() {
+    let mut scene = scenes::cornell_box();
+    scene.camera.image_width = WIDTH as i32;
+    scene.camera.samples = 64;
+    scene.camera.seed = 1;
+    let film = Camera::new(scene.camera).render(scene.world, &scene.lights);
+    assert_eq!((film.width, film.height), (WIDTH, WIDTH));
+
+    // Green wall on the left, red wall on the right, white boxes in between
+    let left = average(&film, 1..4, 8..24);
+    let right = average(&film, 28..31, 8..24);
+    let center = average(&film, 12..20, 12..20);
+    assert_close("left wall", left, [0.0253, 0.0851, 0.0272]);
+    assert_close("right wall", right, [0.1358, 0.0101, 0.0095]);
+    assert_close("center", center, [0.1663, 0.1423, 0.1353]);
+}
@@ -0,0 +1,61 @@
+//! Image files written by `output::save`, read back byte by byte.
+
+use std::{env, fs, process};
+
+use raytracer_cpu::{
+    color::{to_rgb16, to_rgb8, Color},
+    output::{self, SaveOptions},
+};
+
+/// Path in the temporary directory unique to this test process
+fn temp_path(name: &str) -> String {
+    env::temp_dir()
+        .join(format!("raytracer-{}-{name}", process::id()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Write a 2x1 PNG and return its bytes
+fn save_png(name: &str, pixels: &[Color], options: &SaveOptions) -> Vec<u8> {
+    let path = temp_path(name);
+    output::save_with(&path, 2, 1, pixels, options).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    bytes
+}
+
+/// Raw scanlines of a PNG written with a single stored deflate block
+fn scanlines(png: &[u8]) -> &[u8] {
+    // Signature and IHDR, then the IDAT length and type
+    let idat = 8 + 25;
+    assert_eq!(&png[idat + 4..idat + 8], b"IDAT");
+    let length = u32::from_be_bytes(png[idat..idat + 4].try_into().unwrap()) as usize;
+    // zlib header and stored block header before, Adler-32 after
+    &png[idat + 8 + 2 + 5..idat + 8 + length - 4]
+}
+
+#[test]
+fn png_bit_depth_round_trip() {
+    let pixels = [Color::new(0.25, 0.5, 1.0), Color::new(0.0, 0.1, 2.0)];
+
+    let png = save_png("8.png", &pixels, &SaveOptions::default());
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(png[24], 8, "bit depth");
+    let expected: Vec<u8> = std::iter::once(0)
+        .chain(pixels.iter().flat_map(|&c| to_rgb8(c)))
+        .collect();
+    assert_eq!(scanlines(&png), expected);
+
+    let options = SaveOptions { sixteen_bit: true };
+    let png = save_png("16.png", &pixels, &options);
+    assert_eq!(png[24], 16, "bit depth");
+    let expected: Vec<u8> = std::iter::once(0)
+        .chain(
+            pixels
+                .iter()
+                .flat_map(|&c| to_rgb16(c))
+                .flat_map(u16::to_be_bytes),
+        )
+        .collect();
+    assert_eq!(scanlines(&png), expected);
+}
@@ -0,0 +1,99 @@
+//! Scenes built from TOML files by `scenes::file`: which objects are
+//! sampled as lights, and transformations that would make objects vanish.
+
+use raytracer_cpu::{
+    hittables::Hittable,
+    scenes::{file, Scene},
+    vector::Vec3,
+};
+
+fn fixture_path(name: &str) -> String {
+    format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Scene of the lamp mesh, a glowing triangle over (0, 0)-(1, 1) next to a
+/// plain one over (2, 0)-(3, 1), with extra keys for the object
+fn lamp_scene(object: &str) -> Scene {
+    let source = format!("[[objects]]\ntype = \"mesh\"\nfile = \"lamp.obj\"\n{object}");
+    match file::from_str(&source, &fixture_path("scene.toml")) {
+        Ok(scene) => scene,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+#[test]
+fn emissive_mesh_faces_are_lights() {
+    let scene = lamp_scene("");
+    assert_eq!(scene.lights.objects.len(), 1);
+    let light = &scene.lights.objects[0];
+
+    // Only the glowing face is sampled
+    let origin = Vec3::new(1.5, 0.25, 2.0);
+    for _ in 0..100 {
+        let point = origin + light.random(origin, 0.0);
+        assert!(point.x <= 1.0 && point.x + point.y <= 1.0, "{point:?}");
+        assert!(point.z.abs() < 1e-9);
+    }
+    let towards = |x: f64, y: f64| Vec3::new(x, y, 0.0) - origin;
+    assert!(light.pdf_value(origin, towards(0.25, 0.25), 0.0) > 0.0);
+    assert_eq!(light.pdf_value(origin, towards(2.25, 0.25), 0.0), 0.0);
+}
+
+#[test]
+fn emissive_mesh_faces_follow_the_transform() {
+    let scene = lamp_scene("transform = [{ translate = [0.0, 0.0, -3.0] }]\n");
+    let light = &scene.lights.objects[0];
+    let origin = Vec3::new(0.25, 0.25, 2.0);
+    let point = origin + light.random(origin, 0.0);
+    assert!((point.z + 3.0).abs() < 1e-9, "{point:?}");
+}
+
+#[test]
+fn mesh_materials_replace_emissive_faces() {
+    let scene = lamp_scene(
+        "material = \"grey\"\n\n[materials.grey]\ntype = \"lambertian\"\ncolor = [0.5, 0.5, 0.5]\n",
+    );
+    assert!(scene.lights.objects.is_empty());
+}
+
+/// Field, line and message of the error loading `source`
+fn invalid(source: &str) -> (String, usize, String) {
+    match file::from_str(source, "invalid.toml") {
+        Err(file::SceneError::Invalid {
+            field,
+            line,
+            message,
+            ..
+        }) => (field, line, message),
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("scene built from an invalid file"),
+    }
+}
+
+#[test]
+fn singular_keyframes_fail_scenes() {
+    let sphere = |transform: &str| {
+        format!(
+            "[materials.grey]\ntype = \"lambertian\"\ncolor = [0.5, 0.5, 0.5]\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+             material = \"grey\"\ntransform = {transform}\n"
+        )
+    };
+
+    // Flattened at its second keyframe only
+    let (field, line, message) = invalid(&sphere(
+        "[{ scale = [{ time = 0.0, value = 1.0 }, { time = 1.0, value = [1.0, 0.0, 1.0] }] }]",
+    ));
+    assert_eq!((field.as_str(), line), ("objects[0].transform", 5));
+    assert!(message.contains("time 1"), "{message}");
+
+    // A singular matrix next to an animated step
+    let (field, line, _) = invalid(&sphere(
+        "[{ rotate_y = [{ time = 0.0, value = 0.0 }, { time = 2.0, value = 360.0 }] }, \
+         { matrix = [[1.0, 0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]] }]",
+    ));
+    assert_eq!((field.as_str(), line), ("objects[0].transform", 5));
+
+    let (_, _, message) = invalid(&sphere("[{ scale = 0.0 }]"));
+    assert_eq!(message, "transformation is not invertible");
+}
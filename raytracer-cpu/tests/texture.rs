@@ -0,0 +1,47 @@
+//! Lookups of image textures outside of [0, 1]: clamped by default like the
+//! original nearest texel lookup, tiled only when asked for.
+//!
+//! The 37x20 gradient fixture has R = x / 8 and G = y / 8 at texel (x, y),
+//! its first row at the top of the texture (v = 1).
+
+use raytracer_cpu::{
+    color::Color,
+    texture::{ImageTexture, Texture, Wrap},
+    vector::Vec3,
+};
+
+fn gradient() -> ImageTexture {
+    ImageTexture::open(&format!(
+        "{}/tests/fixtures/gradient_zip.exr",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+}
+
+/// Column and row of the texel seen at `uv`
+fn texel(texture: &ImageTexture, uv: (f64, f64)) -> (f64, f64) {
+    let color: Color = texture.value(uv, Vec3::new(0.0, 0.0, 0.0));
+    (8.0 * color.x, 8.0 * color.y)
+}
+
+#[test]
+fn nearest_and_clamped_by_default() {
+    let texture = gradient();
+    assert_eq!(texel(&texture, (0.5, 0.5)), (18.0, 10.0));
+    assert_eq!(texel(&texture, (0.0, 1.0)), (0.0, 0.0));
+
+    // The poles of a sphere keep their own row
+    assert_eq!(texel(&texture, (0.3, 0.0)), (11.0, 19.0));
+    assert_eq!(texel(&texture, (1.0, 1.0)), (36.0, 0.0));
+    assert_eq!(texel(&texture, (1.5, -0.5)), (36.0, 19.0));
+    assert_eq!(texel(&texture, (-2.0, 3.0)), (0.0, 0.0));
+}
+
+#[test]
+fn repeat_is_opt_in() {
+    let texture = gradient().with_wrap(Wrap::Repeat);
+    assert_eq!(texel(&texture, (0.5, 0.5)), (18.0, 10.0));
+    assert_eq!(texel(&texture, (1.5, 1.5)), (18.0, 10.0));
+    assert_eq!(texel(&texture, (-0.5, -0.5)), (18.0, 10.0));
+    // v = 0 is the top row of the next copy
+    assert_eq!(texel(&texture, (0.3, 0.0)), (11.0, 0.0));
+}
@@ -0,0 +1,129 @@
+//! Shadow rays through participating media: they go through to the next
+//! surface, dimmed by the transmittance of the media in between, exact for
+//! constant media and estimated with ratio tracking for heterogeneous ones.
+//!
+//! Every medium fills a sphere of radius 1 around the origin, crossed along
+//! the x axis by rays starting at x = -3.
+
+use std::sync::Arc;
+
+use raytracer_cpu::{
+    bvh::Bvh,
+    hittables::{ArcHittable, Hittable, HittableList, Transform},
+    interval::Interval,
+    material::Lambertian,
+    matrix::Mat4,
+    random,
+    ray::Ray,
+    sphere::Sphere,
+    texture::SolidColor,
+    vector::Vec3,
+    volumes::{ConstantMedium, HeterogeneousMedium},
+};
+
+const SAMPLES: usize = 20_000;
+
+fn sphere(center: Vec3, radius: f64) -> ArcHittable {
+    Arc::new(Sphere::new(
+        center,
+        radius,
+        Arc::new(Lambertian::from_rgb(0.5, 0.5, 0.5)),
+    ))
+}
+
+fn along_x(speed: f64) -> Ray {
+    Ray::new(Vec3::new(-3.0, 0.0, 0.0), Vec3::new(speed, 0.0, 0.0), 0.0)
+}
+
+/// Half the density of 2 everywhere in the sphere
+fn heterogeneous() -> HeterogeneousMedium {
+    HeterogeneousMedium::new(
+        sphere(Vec3::new(0.0, 0.0, 0.0), 1.0),
+        Arc::new(SolidColor::from_rgb(0.5, 0.5, 0.5)),
+        2.0,
+        Arc::new(SolidColor::from_rgb(1.0, 1.0, 1.0)),
+    )
+}
+
+fn mean_and_variance(values: &[f64]) -> (f64, f64) {
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    (mean, variance)
+}
+
+#[test]
+fn constant_media_are_exact() {
+    let medium = ConstantMedium::from_color(
+        sphere(Vec3::new(0.0, 0.0, 0.0), 1.0),
+        0.5,
+        Vec3::new(1.0, 1.0, 1.0),
+    );
+    assert!(medium
+        .hit_surface(&along_x(1.0), Interval::positive())
+        .is_none());
+
+    let expected = (-0.5f64 * 2.0).exp();
+    for speed in [1.0, 2.0] {
+        let transmittance = medium.transmittance(&along_x(speed), Interval::positive());
+        assert!((transmittance - expected).abs() < 1e-9, "{transmittance}");
+    }
+
+    // Up to the center only
+    let transmittance = medium.transmittance(&along_x(1.0), Interval::new(0.001, 3.0));
+    assert!(
+        (transmittance - (-0.5f64).exp()).abs() < 1e-9,
+        "{transmittance}"
+    );
+}
+
+#[test]
+fn ratio_tracking_is_less_noisy_than_delta_tracking() {
+    random::seed(3);
+    let medium = heterogeneous();
+    let ray = along_x(1.0);
+    let expected = (-2.0f64).exp();
+
+    let ratio: Vec<f64> = (0..SAMPLES)
+        .map(|_| medium.transmittance(&ray, Interval::positive()))
+        .collect();
+    let delta: Vec<f64> = (0..SAMPLES)
+        .map(|_| match medium.hit(&ray, Interval::positive()) {
+            Some(_) => 0.0,
+            None => 1.0,
+        })
+        .collect();
+
+    let (ratio_mean, ratio_variance) = mean_and_variance(&ratio);
+    let (delta_mean, delta_variance) = mean_and_variance(&delta);
+    assert!((ratio_mean - expected).abs() < 0.005, "{ratio_mean}");
+    assert!((delta_mean - expected).abs() < 0.01, "{delta_mean}");
+    assert!(ratio_variance < 0.5 * delta_variance);
+}
+
+#[test]
+fn shadow_rays_reach_surfaces_behind_media() {
+    random::seed(4);
+    // The medium is moved into place, the wall is behind it
+    let medium = Transform::new(
+        Arc::new(heterogeneous()),
+        Mat4::translation(Vec3::new(1.0, 0.0, 0.0)),
+    );
+    let mut objects = HittableList::new();
+    objects.add(Arc::new(medium));
+    objects.add(sphere(Vec3::new(10.0, 0.0, 0.0), 5.0));
+    let world = Bvh::from(objects);
+
+    let ray = along_x(1.0);
+    let wall = world.hit_surface(&ray, Interval::positive()).unwrap();
+    assert!((wall.distance - 8.0).abs() < 1e-9, "{}", wall.distance);
+
+    let transmittances: Vec<f64> = (0..SAMPLES)
+        .map(|_| world.transmittance(&ray, Interval::new(0.001, wall.distance)))
+        .collect();
+    let (mean, _) = mean_and_variance(&transmittances);
+    assert!((mean - (-2.0f64).exp()).abs() < 0.005, "{mean}");
+
+    // Nothing in front of the medium
+    let short = Interval::new(0.001, 1.5);
+    assert_eq!(world.transmittance(&ray, short), 1.0);
+}